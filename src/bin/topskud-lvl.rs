@@ -0,0 +1,268 @@
+//! Command-line tools for working with level files without opening the game
+#![warn(clippy::all)]
+
+use std::collections::BTreeMap;
+use std::env::args;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use topskud::game::world::{Level, LEVEL_VERSION, format_version};
+
+const USAGE: &str = "\
+Usage: topskud-lvl <command> <args>...
+
+Commands:
+    info <level>...                 Print the contents of levels
+    convert <input> <output>        Convert a level between the binary and the text (.toml) format
+    validate <level or dir>...      Check levels for problems that make them unplayable
+    upgrade <level or dir>...       Rewrite binary levels in an old format in the current format
+
+Campaign (.cmp) files stand for the levels they list.";
+
+fn main() {
+    let mut args = args().skip(1);
+    let command = args.next().unwrap_or_default();
+    let args: Vec<String> = args.collect();
+
+    let ok = match (&*command, args.len()) {
+        ("info", n) if n > 0 => every(&args, |p| info(Path::new(p))),
+        ("convert", 2) => convert(Path::new(&args[0]), Path::new(&args[1])),
+        ("validate", n) if n > 0 => for_levels(&args, validate),
+        ("upgrade", n) if n > 0 => for_levels(&args, upgrade),
+        _ => {
+            eprintln!("{}", USAGE);
+            false
+        }
+    };
+
+    if !ok {
+        exit(1);
+    }
+}
+
+/// Runs `f` on every item, even after it has failed for one, returning whether it succeeded for all of them
+fn every<T>(items: &[T], mut f: impl FnMut(&T) -> bool) -> bool {
+    let mut ok = true;
+    for item in items {
+        ok &= f(item);
+    }
+    ok
+}
+
+/// Runs `f` on every level file in the given paths, failing if there are none
+fn for_levels(paths: &[String], f: fn(&Path) -> bool) -> bool {
+    let files = match level_files(paths) {
+        Some(files) => files,
+        None => return false,
+    };
+    if files.is_empty() {
+        eprintln!("No level files found in {}", paths.join(", "));
+        return false;
+    }
+    every(&files, |p| f(p))
+}
+
+/// All level files in the given paths, looking through directories recursively
+/// and reading the levels listed in campaign files
+///
+/// Returns `None` if any of the paths couldn't be read or a campaign lists itself.
+fn level_files(paths: &[String]) -> Option<Vec<PathBuf>> {
+    /// `campaigns` are the campaign files being read on the way to `path`
+    fn walk(path: &Path, files: &mut Vec<PathBuf>, campaigns: &mut Vec<PathBuf>) -> bool {
+        if path.is_dir() {
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+                    entries.sort();
+                    entries.retain(|entry| entry.is_dir() || is_level(entry) || is_campaign(entry));
+                    every(&entries, |entry| walk(entry, files, campaigns))
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    false
+                }
+            }
+        } else if is_campaign(path) {
+            let canonical = match path.canonicalize() {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    return false;
+                }
+            };
+            if campaigns.contains(&canonical) {
+                eprintln!("{}: campaign lists itself", path.display());
+                return false;
+            }
+            // Campaigns list their levels relative to where the game is run from, just like the game reads them
+            match fs::read_to_string(path) {
+                Ok(s) => {
+                    campaigns.push(canonical);
+                    let lines: Vec<_> = s.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+                    let ok = every(&lines, |line| walk(Path::new(line), files, campaigns));
+                    campaigns.pop();
+                    ok
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    false
+                }
+            }
+        } else {
+            if !files.iter().any(|f| f == path) {
+                files.push(path.to_owned());
+            }
+            true
+        }
+    }
+    fn is_level(path: &Path) -> bool {
+        path.extension().map(|ext| ext == "lvl" || ext == "toml").unwrap_or(false)
+    }
+    fn is_campaign(path: &Path) -> bool {
+        path.extension().map(|ext| ext == "cmp").unwrap_or(false)
+    }
+
+    let mut files = Vec::new();
+    let ok = every(paths, |path| walk(Path::new(path), &mut files, &mut Vec::new()));
+    if ok {
+        Some(files)
+    } else {
+        None
+    }
+}
+
+fn load(path: &Path) -> Option<Level> {
+    match Level::load(path) {
+        Ok(level) => Some(level),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn info(path: &Path) -> bool {
+    let level = match load(path) {
+        Some(l) => l,
+        None => return false,
+    };
+
+    println!("{}:", path.display());
+    if let Ok(bytes) = fs::read(path) {
+        if path.extension().map(|ext| ext != "toml").unwrap_or(true) {
+            println!("  format version: {}", format_version(&bytes));
+        }
+    }
+    println!("  size: {}x{}", level.grid.width(), level.grid.height());
+    let palette: Vec<_> = level.palette.iter().collect();
+    println!("  palette: {}", palette.join(", "));
+    match level.start_point {
+        Some(p) => println!("  start: ({:.1}, {:.1})", p.x, p.y),
+        None => println!("  start: none"),
+    }
+    match level.exit {
+        Some(p) => println!("  exit: ({:.1}, {:.1})", p.x, p.y),
+        None => println!("  exit: none"),
+    }
+    println!("  enemies: {}", level.enemies.len());
+
+    let mut kinds = BTreeMap::new();
+    for enemy in &level.enemies {
+        *kinds.entry(enemy.kind.id).or_insert(0) += 1;
+    }
+    for (id, n) in kinds {
+        println!("    {}: {}", id, n);
+    }
+    println!("  patrols: {}", level.enemies.iter().filter(|e| !e.patrol.route.is_empty()).count());
+    println!("  enemy grenades: {}", level.enemies.iter().map(|e| u32::from(e.pl.wep.utilities.grenades)).sum::<u32>());
+    println!("  intels: {}", level.intels.len());
+    println!("  pickups: {}", level.pickups.len());
+    println!("  decals: {}", level.decals.len());
+    println!("  doors: {}", level.doors.len());
+    println!("  alarms: {}", level.alarms.len());
+    println!("  weapons: {}", level.weapons.len());
+
+    let mut weapons = BTreeMap::new();
+    for wep in &level.weapons {
+        *weapons.entry(wep.weapon.id).or_insert(0) += 1;
+    }
+    for (id, n) in weapons {
+        println!("    {}: {}", id, n);
+    }
+    if !level.unknown_weapons.is_empty() {
+        let mut unknown = BTreeMap::new();
+        for (_, id) in &level.unknown_weapons {
+            *unknown.entry(&**id).or_insert(0) += 1;
+        }
+        println!("  unknown weapons: {}", level.unknown_weapons.len());
+        for (id, n) in unknown {
+            println!("    {}: {}", id, n);
+        }
+    }
+
+    true
+}
+
+fn convert(input: &Path, output: &Path) -> bool {
+    let level = match load(input) {
+        Some(l) => l,
+        None => return false,
+    };
+    match level.save(output) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{}: {}", output.display(), e);
+            false
+        }
+    }
+}
+
+fn validate(path: &Path) -> bool {
+    let level = match load(path) {
+        Some(l) => l,
+        None => return false,
+    };
+
+    let diagnostics = level.validate();
+    if diagnostics.is_empty() {
+        println!("{}: ok", path.display());
+    }
+    for d in &diagnostics {
+        let severity = if d.problem.is_error() { "error" } else { "warning" };
+        println!("{}: {}: {}", path.display(), severity, d);
+    }
+    diagnostics.iter().all(|d| !d.problem.is_error())
+}
+
+fn upgrade(path: &Path) -> bool {
+    // Text levels are always read into the current version
+    if path.extension().map(|ext| ext == "toml").unwrap_or(false) {
+        return true;
+    }
+    let version = match fs::read(path) {
+        Ok(bytes) => format_version(&bytes),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return false;
+        }
+    };
+    if version >= LEVEL_VERSION {
+        return true;
+    }
+
+    let level = match load(path) {
+        Some(l) => l,
+        None => return false,
+    };
+    match level.save(path) {
+        Ok(()) => {
+            println!("{}: upgraded from version {} to {}", path.display(), version, LEVEL_VERSION);
+            true
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            false
+        }
+    }
+}
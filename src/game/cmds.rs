@@ -1,9 +1,9 @@
 use std::collections::HashMap;
-use super::{Console, State, GameState, Command, CommandError, Content, StateSwitch};
+use super::{Console, State, GameState, Command, CommandError, Content, StateSwitch, replay::Replay, world::Level, difficulty::Difficulty};
 
 use crate::{
     util::dbg_strs,
-    obj::{health::Health, weapon::WEAPONS},
+    obj::{enemy::Enemy, weapon::WEAPONS},
 };
 use ggez::Context;
 
@@ -97,7 +97,7 @@ pub(super) fn commands() -> HashMap<String, Command> {
                 let (health, wep) = if let Some(world) = gs.get_world() {
                     (world.player.health, world.player.wep.clone())
                 } else {
-                    (Health::default(), Default::default())
+                    (state.difficulty.health, Default::default())
                 };
 
                 state.switch(StateSwitch::PlayWith{health, wep, lvl: Box::new(lvl)});
@@ -108,6 +108,54 @@ pub(super) fn commands() -> HashMap<String, Command> {
         } else {
             Err(NoCampaign)
         }},
+        "saverpl" => {
+            let replay = gs.get_replay().ok_or(NoReplay)?;
+            let &path = args.get(1).ok_or(InvalidArg)?;
+            replay.save(path).map_err(|_| ReplayFile)?;
+            info!("Replay saved to {}", path);
+            Ok(())
+        },
+        "playrpl" => {
+            let &path = args.get(1).ok_or(InvalidArg)?;
+            let replay = Replay::load(path).map_err(|e| {
+                warn!("Couldn't load replay {}: {}", path, e);
+                ReplayFile
+            })?;
+
+            let lvl = match state.content {
+                Content::File(ref f) => Level::load(f).ok().filter(|l| l.checksum() == replay.level_hash),
+                Content::Campaign(ref cmp) => cmp.levels.iter().find(|l| l.checksum() == replay.level_hash).cloned(),
+                Content::None => None,
+            }.ok_or(NoSuchLevel)?;
+
+            state.switch(StateSwitch::Replay{lvl: Box::new(lvl), replay: Box::new(replay)});
+            Ok(())
+        },
+        "ai" => {
+            let world = gs.get_world().ok_or(NoWorld)?;
+            let show = |i: usize, enemy: &Enemy| {
+                info!("{}: {} at ({:.0}, {:.0}) with {:.0} hp, {}", i, enemy.kind.name, enemy.pl.obj.pos.x, enemy.pl.obj.pos.y, enemy.pl.health.hp, enemy.behaviour);
+            };
+            if let Some(i) = args.get(1) {
+                let i = i.parse().map_err(|_| InvalidArg)?;
+                let enemy = world.enemies.get(i).ok_or(NoSuchEnemy)?;
+                show(i, enemy);
+            } else {
+                for (i, enemy) in world.enemies.iter().enumerate() {
+                    show(i, enemy);
+                }
+            }
+            Ok(())
+        },
+        "difficulty" => {
+            if let Some(&id) = args.get(1) {
+                state.difficulty = Difficulty::get(id).ok_or(NoSuchDifficulty)?;
+                info!("Difficulty set to {} from the next level", state.difficulty.name);
+            } else {
+                info!("Difficulty is {}", state.difficulty.name);
+            }
+            Ok(())
+        },
         "gg" => {
             let world = gs.get_mut_world().ok_or(NoWorld)?;
             world.player.wep.utilities.grenades += 3;
@@ -0,0 +1,115 @@
+use crate::{
+    util::{sstr, Sstr, DEG2RAD},
+    obj::health::Health,
+};
+
+use lazy_static::lazy_static;
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de::Error};
+
+use std::fs::File;
+use std::io::Read;
+use std::collections::HashMap;
+
+/// The difficulty used when nothing else is chosen
+pub const DEFAULT_DIFFICULTY: &str = "normal";
+/// Difficulties in the order the menu goes through them
+pub const DIFFICULTY_ORDER: [&str; 4] = ["easy", "normal", "hard", "custom"];
+
+lazy_static!{
+    pub static ref DIFFICULTIES: HashMap<&'static str, Difficulty> = {
+        let mut file = File::open("resources/difficulties.toml").expect("difficulties.toml file");
+        let mut file_contents = String::new();
+        file.read_to_string(&mut file_contents).expect("Reading to succeed");
+
+        let templates: HashMap<Box<str>, DifficultyTemplate> = toml::from_str(&file_contents).expect("well-defined difficulties");
+        templates.into_iter().map(|(k, v)| {
+            let k = sstr(k);
+            (k, v.build(k))
+        }).collect()
+    };
+}
+
+/// How hard the enemies are on the player
+#[derive(Debug)]
+pub struct Difficulty {
+    pub id: Sstr,
+    pub name: Sstr,
+    /// Seconds added to the reaction time of every enemy
+    pub reaction_time: f32,
+    /// Most radians an enemy's shot can be off by besides the weapon's spray
+    pub aim_error: f32,
+    /// Factor on how fast enemies can turn
+    pub turn_speed: f32,
+    /// Factor on the damage dealt to the player
+    pub damage_taken: f32,
+    /// Health the player starts with when not carrying any over
+    pub health: Health,
+}
+
+impl Difficulty {
+    #[inline]
+    pub fn default_difficulty() -> &'static Self {
+        &DIFFICULTIES[DEFAULT_DIFFICULTY]
+    }
+    /// Looks up a difficulty by its id
+    #[inline]
+    pub fn get(id: &str) -> Option<&'static Self> {
+        DIFFICULTIES.get(id)
+    }
+    /// The difficulty after this one in the menu, going back to the first after the last
+    pub fn next(&self) -> &'static Self {
+        let i = DIFFICULTY_ORDER.iter().position(|&id| id == self.id).unwrap_or(0);
+        &DIFFICULTIES[DIFFICULTY_ORDER[(i + 1) % DIFFICULTY_ORDER.len()]]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DifficultyTemplate {
+    #[serde(deserialize_with = "crate::util::deserialize_sstr")]
+    name: Sstr,
+    reaction_time: f32,
+    aim_error: f32,
+    turn_speed: f32,
+    damage_taken: f32,
+    hp: f32,
+    armour: f32,
+}
+
+impl DifficultyTemplate {
+    fn build(self, id: &'static str) -> Difficulty {
+        let DifficultyTemplate {
+            name,
+            reaction_time,
+            aim_error,
+            turn_speed,
+            damage_taken,
+            hp,
+            armour,
+        } = self;
+
+        Difficulty {
+            id,
+            name,
+            reaction_time,
+            aim_error: aim_error * DEG2RAD,
+            turn_speed,
+            damage_taken,
+            health: Health {
+                hp,
+                armour,
+            },
+        }
+    }
+}
+
+/// Serialize a difficulty as its id
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn difficulty_ser<S: Serializer>(d: &&'static Difficulty, ser: S) -> Result<S::Ok, S::Error> {
+    d.id.serialize(ser)
+}
+
+/// Deserialize a difficulty from its id
+pub fn difficulty_des<'de, D: Deserializer<'de>>(des: D) -> Result<&'static Difficulty, D::Error> {
+    let id = <Box<str>>::deserialize(des)?;
+    DIFFICULTIES.get(&*id).ok_or_else(|| D::Error::custom(format!("no difficulty called `{}'", id)))
+}
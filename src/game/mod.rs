@@ -26,19 +26,29 @@ use lazy_static::lazy_static;
 /// Stuff related to things in the world
 pub mod world;
 pub mod states;
+pub mod replay;
+pub mod difficulty;
 
 use self::states::menu::Menu;
 use self::world::Statistics;
+use self::replay::Replay;
+use self::difficulty::Difficulty;
 
 pub enum StateSwitch {
     Menu,
     Editor(Option<Level>),
     Play(Level),
+    /// Plays a level from the editor with what the enemies are doing shown
+    PlayTest(Level),
     PlayWith{
         lvl: Box<Level>,
         health: Health,
         wep: WepSlots,
     },
+    Replay{
+        lvl: Box<Level>,
+        replay: Box<Replay>,
+    },
     Lose(Box<Statistics>),
     Win(Box<Statistics>),
 }
@@ -73,6 +83,9 @@ pub trait GameState {
     fn get_mut_world(&mut self) -> Option<&mut world::World> {
         None
     }
+    fn get_replay(&self) -> Option<&Replay> {
+        None
+    }
 }
 
 lazy_static! {
@@ -144,6 +157,10 @@ enum CommandError {
     InvalidArg,
     NoSuchLevel,
     NoSuchWeapon,
+    NoSuchEnemy,
+    NoSuchDifficulty,
+    NoReplay,
+    ReplayFile,
 }
 
 impl Display for CommandError {
@@ -155,6 +172,10 @@ impl Display for CommandError {
             InvalidArg => "Invalid argument".fmt(f),
             NoSuchLevel => "No such level".fmt(f),
             NoSuchWeapon => "No such weapon".fmt(f),
+            NoSuchEnemy => "No such enemy".fmt(f),
+            NoSuchDifficulty => "No such difficulty".fmt(f),
+            NoReplay => "No replay".fmt(f),
+            ReplayFile => "Couldn't read or write replay file".fmt(f),
         }
     }
 }
@@ -252,6 +273,7 @@ pub struct State {
     offset: Vector2,
     switch_state: Option<StateSwitch>,
     content: Content,
+    difficulty: &'static Difficulty,
 }
 
 const DESIRED_FPS: u32 = 60;
@@ -260,7 +282,7 @@ pub(crate) const DELTA: f32 = 1. / DESIRED_FPS as f32;
 
 impl Master {
     /// Make a new state object
-    pub fn new(ctx: &mut Context, arg: &str) -> GameResult<Self> {
+    pub fn new(ctx: &mut Context, arg: &str, difficulty: &'static Difficulty) -> GameResult<Self> {
         // Initialise assets
         let assets = Assets::new(ctx)?;
         let mut mplayer = MediaPlayer::new();
@@ -280,6 +302,7 @@ impl Master {
 
         let mut state = State {
             content,
+            difficulty,
             switch_state: None,
             assets,
             mplayer,
@@ -321,6 +344,8 @@ impl EventHandler for Master {
             self.gs = match gsb {
                 PlayWith{lvl, health, wep} => states::play::Play::new(ctx, &mut self.state, *lvl, Some((health, wep))),
                 Play(lvl) => states::play::Play::new(ctx, &mut self.state, lvl, None),
+                PlayTest(lvl) => states::play::Play::play_test(ctx, &mut self.state, lvl),
+                Replay{lvl, replay} => states::play::Play::replay(ctx, &mut self.state, *lvl, *replay),
                 Menu => states::menu::Menu::new(ctx, &mut self.state),
                 Editor(l) => states::editor::Editor::new(&self.state, l),
                 Win(stats) => states::win::Win::new(ctx, &mut self.state, *stats),
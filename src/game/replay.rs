@@ -0,0 +1,240 @@
+use std::path::Path;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+use crate::obj::{
+    health::Health,
+    player::{ActiveSlot, WepSlots},
+    grenade::Utilities,
+    weapon::{WeaponInstance, WEAPONS, DEFAULT_MELEE},
+};
+use super::{
+    world::{Input, Level},
+    difficulty::{Difficulty, difficulty_ser, difficulty_des},
+};
+
+use ggez::{GameResult, GameError};
+
+/// What every replay file starts with, followed by the version of the format
+const MAGIC: &[u8; 8] = b"TSKREPL\n";
+/// Version of the replay format that gets written
+///
+/// Has to be bumped whenever anything a replay holds changes how it's serialized, like the input.
+/// Files without the magic header are treated as version 0.
+pub const REPLAY_VERSION: u16 = 2;
+
+/// A recording of a play session of a level that can be played back tick by tick
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    /// Checksum of the level that was played
+    pub level_hash: u64,
+    /// Seed of the world's random number generator
+    pub seed: u64,
+    /// What the player started with if not the defaults
+    pub loadout: Option<Loadout>,
+    #[serde(serialize_with = "difficulty_ser", deserialize_with = "difficulty_des")]
+    pub difficulty: &'static Difficulty,
+    /// The player's input for every tick, stored as how many ticks in a row each input was held for
+    inputs: Vec<(u32, Input)>,
+}
+
+/// Where in a replay's input playback has gotten to
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Playhead {
+    run: usize,
+    tick: u32,
+}
+
+impl Replay {
+    pub fn new(level: &Level, seed: u64, pl: Option<&(Health, WepSlots)>, difficulty: &'static Difficulty) -> Self {
+        Replay {
+            level_hash: level.checksum(),
+            seed,
+            loadout: pl.map(|(health, wep)| Loadout::new(*health, wep)),
+            difficulty,
+            inputs: Vec::new(),
+        }
+    }
+    /// Records the input for the next tick
+    pub fn push(&mut self, input: &Input) {
+        match self.inputs.last_mut() {
+            Some((ticks, last)) if last == input => *ticks += 1,
+            _ => self.inputs.push((1, input.clone())),
+        }
+    }
+    /// The input for the tick at `head` or `None` if the recording has ended
+    pub fn next_input(&self, head: &mut Playhead) -> Option<&Input> {
+        let (ticks, input) = self.inputs.get(head.run)?;
+        head.tick += 1;
+        if head.tick >= *ticks {
+            head.run += 1;
+            head.tick = 0;
+        }
+        Some(input)
+    }
+    /// How many ticks were recorded
+    pub fn len(&self) -> usize {
+        self.inputs.iter().map(|&(ticks, _)| ticks as usize).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> GameResult<Self> {
+        let bytes = fs::read(path)?;
+        let version = if bytes.starts_with(MAGIC) && bytes.len() >= MAGIC.len() + 2 {
+            u16::from_le_bytes([bytes[MAGIC.len()], bytes[MAGIC.len() + 1]])
+        } else {
+            0
+        };
+        // Old replays can't be played back anyway since the game has changed since they were recorded
+        if version != REPLAY_VERSION {
+            return Err(GameError::ResourceLoadError(
+                format!("Replay is in version {} of the format but only version {} can be played back", version, REPLAY_VERSION)
+            ));
+        }
+
+        bincode::deserialize(&bytes[MAGIC.len() + 2..])
+            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))
+    }
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> GameResult<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        bincode::serialize_into(file, self)
+            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedWeapon {
+    id: Box<str>,
+    cur_clip: u16,
+    ammo: u16,
+}
+
+impl SavedWeapon {
+    fn new(wep: &WeaponInstance<'static>) -> Self {
+        SavedWeapon {
+            id: wep.weapon.id.into(),
+            cur_clip: wep.cur_clip,
+            ammo: wep.ammo,
+        }
+    }
+    fn restore(&self) -> Option<WeaponInstance<'static>> {
+        let weapon = WEAPONS.get(&*self.id)?;
+        Some(WeaponInstance {
+            cur_clip: self.cur_clip,
+            ammo: self.ammo,
+            .. weapon.make_instance()
+        })
+    }
+}
+
+/// Health and weapons of a player carried over from a previous level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loadout {
+    health: Health,
+    active: ActiveSlot,
+    grenades: u8,
+    holster: Option<SavedWeapon>,
+    holster2: Option<SavedWeapon>,
+    sling: Option<SavedWeapon>,
+    melee: SavedWeapon,
+}
+
+impl Loadout {
+    pub fn new(health: Health, wep: &WepSlots) -> Self {
+        Loadout {
+            health,
+            active: wep.active,
+            grenades: wep.utilities.grenades,
+            holster: wep.holster.as_ref().map(SavedWeapon::new),
+            holster2: wep.holster2.as_ref().map(SavedWeapon::new),
+            sling: wep.sling.as_ref().map(SavedWeapon::new),
+            melee: SavedWeapon::new(&wep.melee),
+        }
+    }
+    pub fn restore(&self) -> (Health, WepSlots) {
+        let wep = WepSlots {
+            active: self.active,
+            utilities: Utilities {
+                grenades: self.grenades,
+            },
+            holster: self.holster.as_ref().and_then(SavedWeapon::restore),
+            holster2: self.holster2.as_ref().and_then(SavedWeapon::restore),
+            sling: self.sling.as_ref().and_then(SavedWeapon::restore),
+            melee: self.melee.restore().unwrap_or_else(|| WEAPONS[DEFAULT_MELEE].make_instance()),
+        };
+
+        (self.health, wep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::world::Action;
+
+    use std::env::temp_dir;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// A path in the temporary directory no other test run is using
+    fn temp_path(name: &str) -> PathBuf {
+        temp_dir().join(format!("topskud_test_{}_{}.rpl", name, process::id()))
+    }
+
+    fn new_replay() -> Replay {
+        Replay::new(&Level::new(Default::default(), 2, 2), 42, None, Difficulty::default_difficulty())
+    }
+
+    #[test]
+    fn replay_round_trips() {
+        let path = temp_path("replay");
+        let mut replay = new_replay();
+        replay.push(&Input::default());
+
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.level_hash, replay.level_hash);
+        assert_eq!(loaded.len(), 1);
+    }
+
+    #[test]
+    fn repeated_inputs_are_stored_once() {
+        let mut replay = new_replay();
+        let shooting = Input {
+            actions: vec![Action::Shoot],
+            .. Input::default()
+        };
+        for _ in 0..100 {
+            replay.push(&Input::default());
+        }
+        replay.push(&shooting);
+        replay.push(&Input::default());
+
+        assert_eq!(replay.inputs.len(), 3);
+        assert_eq!(replay.len(), 102);
+
+        let mut head = Playhead::default();
+        let played: Vec<_> = std::iter::from_fn(|| replay.next_input(&mut head).cloned()).collect();
+        assert_eq!(played.len(), 102);
+        assert_eq!(played[100], shooting);
+        assert_eq!(played[101], Input::default());
+    }
+
+    #[test]
+    fn unversioned_replay_is_rejected() {
+        let path = temp_path("old_replay");
+        let replay = new_replay();
+        fs::write(&path, bincode::serialize(&replay).unwrap()).unwrap();
+
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_err());
+    }
+}
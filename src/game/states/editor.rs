@@ -3,16 +3,17 @@ use crate::{
         ver,
         hor,
         sstr,
-        TRANS,
+        TRANS, RED,
+        angle_to_vec,
         Vector2, Point2},
     io::tex::PosText,
     ext::BoolExt,
     game::{
         DELTA, Content, GameState, State, StateSwitch,
-        world::{Grid, Level, Palette},
+        world::{Grid, Level, Palette, Diagnostic, tile_centre},
         event::{Event::{self, Key, Mouse}, MouseButton as Mb, KeyCode, KeyMods}
     },
-    obj::{Object, enemy::Enemy, decal::Decal, pickup::PICKUPS, weapon::WEAPONS}
+    obj::{Object, enemy::{Enemy, Waypoint, ARCHETYPES}, decal::Decal, door::Door, alarm::Alarm, pickup::PICKUPS, weapon::WEAPONS}
 };
 use ggez::{
     Context, GameResult,
@@ -32,16 +33,20 @@ use std::fs::File;
 enum Tool {
     Inserter(Insertion),
     Selector(Selection),
+    /// Adding waypoints to the patrol route of the enemy with the index
+    Patrol(usize),
 }
 
 #[derive(Debug, Clone, Copy)]
 enum Insertion {
     Material(u8),
     Intel,
-    Enemy{rot: f32},
+    Enemy{rot: f32, kind: &'static str},
     Pickup(u8),
     Weapon(&'static str),
     Decal{spr: &'static str, rot: f32},
+    Door{rot: f32},
+    Alarm{rot: f32},
     Exit,
 }
 impl Insertion {
@@ -50,11 +55,13 @@ impl Insertion {
         match *self {
             Material(_) => panic!("Get it yourself. I don't have the palette"),
             Intel => "common/intel",
-            Enemy{..} => "common/enemy",
+            Enemy{kind, ..} => &*ARCHETYPES[kind].sprite,
             Exit => "common/goal",
             Pickup(i) => PICKUPS[i as usize].spr,
             Weapon(wep) => &*WEAPONS[wep].entity_sprite, 
             Decal{spr, ..} => spr,
+            Door{..} => "common/door",
+            Alarm{..} => "common/alarm",
         }
     }
 }
@@ -64,10 +71,12 @@ impl ::std::cmp::PartialEq for Insertion {
         match (self, rhs) {
             (Material(m), Material(n)) if m == n => true,
             (Intel, Intel) => true,
-            (Enemy{..}, Enemy{..}) => true,
+            (Enemy{kind, ..}, Enemy{kind: kind2, ..}) if kind == kind2 => true,
             (Pickup(i), Pickup(j)) if i == j => true,
             (Weapon(i), Weapon(j)) if i == j => true,
             (Decal{spr, ..}, Decal{spr: spr2, ..}) if spr == spr2 => true,
+            (Door{..}, Door{..}) => true,
+            (Alarm{..}, Alarm{..}) => true,
             (Exit, Exit) => true,
             _ => false
         }
@@ -82,6 +91,8 @@ struct Selection {
     pickups: Vec<usize>,
     weapons: Vec<usize>,
     decals: Vec<usize>,
+    doors: Vec<usize>,
+    alarms: Vec<usize>,
     moving: Option<Point2>,
 }
 
@@ -93,6 +104,7 @@ struct EditorFile {
 #[derive(Debug, Serialize, Deserialize)]
 struct EditorPalettes {
     materials: Vec<String>,
+    enemies: Vec<String>,
     weapons: Vec<String>,
     decals: Vec<String>,
 }
@@ -109,6 +121,9 @@ pub struct Editor {
     draw_visibility_cones: bool,
     rotation_speed: f32,
     snap_on_grid: bool,
+    /// Problems with the level if they're being shown
+    diagnostics: Option<Vec<Diagnostic>>,
+    diagnostics_text: PosText,
 }
 
 
@@ -164,9 +179,10 @@ impl Editor {
     pub fn new(s: &State, level: Option<Level>) -> GameResult<Box<dyn GameState>> {
         let mat_text = s.assets.text(Point2::new(2., 18.0)).and_text("Materials:");
         let mut entities = vec![
-            Insertion::Enemy{rot: 0.},
             Insertion::Exit,
             Insertion::Intel,
+            Insertion::Door{rot: 0.},
+            Insertion::Alarm{rot: 0.},
             Insertion::Pickup(0),
             Insertion::Pickup(1),
             Insertion::Pickup(2),
@@ -175,13 +191,14 @@ impl Editor {
             Insertion::Pickup(5),
         ];
 
-        let EditorFile{palettes: EditorPalettes{materials, weapons, decals}} = {
+        let EditorFile{palettes: EditorPalettes{materials, enemies, weapons, decals}} = {
             let mut file = File::open("resources/editor.toml").unwrap();
             let mut s = String::new();
             file.read_to_string(&mut s).unwrap();
             
             toml::from_str(&s).unwrap()
         };
+        entities.splice(0..0, enemies.into_iter().map(|kind| Insertion::Enemy{rot: 0., kind: sstr(kind)}));
         entities.extend(weapons.into_iter().map(|wep| Insertion::Weapon(sstr(wep))));
         entities.extend(decals.into_iter().map(|dec| Insertion::Decal{rot: 0., spr: sstr(dec)}));
 
@@ -218,6 +235,8 @@ impl Editor {
             level,
             rotation_speed: 0.,
             snap_on_grid: false,
+            diagnostics: None,
+            diagnostics_text: s.assets.text_sized(Point2::new(2., 68.), 14.).and_text(""),
         }))
     }
     fn mousepos(&self, s: &State) -> Point2 {
@@ -228,9 +247,29 @@ impl Editor {
         }
         mp
     }
+    /// Checks the level for problems again
+    fn revalidate(&mut self) {
+        let diagnostics = self.level.validate();
+
+        let text = if diagnostics.is_empty() {
+            "No problems".to_owned()
+        } else {
+            diagnostics.iter()
+                .map(|d| format!("{}: {}\n", if d.problem.is_error() { "Error" } else { "Warning" }, d))
+                .collect()
+        };
+        self.diagnostics_text.update(0, text).unwrap();
+        self.diagnostics = Some(diagnostics);
+    }
 }
 
 const START_X: f32 = 103.;
+
+/// Centre of the tile the point is in
+fn snap_to_tile(p: Point2) -> Point2 {
+    let (x, y) = Grid::snap(p);
+    tile_centre(x, y)
+}
 const YELLOW: Color = Color{r: 1., g: 1., b: 0., a: 1.};
 
 impl GameState for Editor {
@@ -240,8 +279,9 @@ impl GameState for Editor {
         self.pos += v * DELTA;
 
         match self.current {
-            Tool::Inserter(Insertion::Enemy{ref mut rot}) => *rot += self.rotation_speed * DELTA,
+            Tool::Inserter(Insertion::Enemy{ref mut rot, ..}) => *rot += self.rotation_speed * DELTA,
             Tool::Inserter(Insertion::Decal{ref mut rot, ..}) => *rot += self.rotation_speed * DELTA,
+            Tool::Inserter(Insertion::Alarm{ref mut rot}) => *rot += self.rotation_speed * DELTA,
             _ => (),
         }
         Ok(())
@@ -318,9 +358,37 @@ impl GameState for Editor {
                 }
             }
             if self.draw_visibility_cones {
-                enemy.draw_visibility_cone(ctx, 512.)?;
+                enemy.draw_visibility_cone(ctx)?;
+            }
+            let route = &enemy.patrol.route;
+            if !route.is_empty() {
+                let color = if self.current == Tool::Patrol(i) { YELLOW } else { TRANS };
+                let mut points: Vec<_> = route.iter().map(|w| w.pos).collect();
+                points.push(route[0].pos);
+                if route.len() > 1 {
+                    let mesh = Mesh::new_polyline(ctx, DrawMode::stroke(1.5), &points, color)?;
+                    graphics::draw(ctx, &mesh, DrawParam::default())?;
+                }
+                for w in route {
+                    // Bigger circles for waypoints that are stayed at longer
+                    let mesh = Mesh::new_circle(ctx, DrawMode::stroke(1.5), w.pos, 4. + 2. * w.wait.min(8.), 0.5, color)?;
+                    graphics::draw(ctx, &mesh, DrawParam::default())?;
+                    if let Some(rot) = w.facing {
+                        let mesh = Mesh::new_line(ctx, &[w.pos, w.pos + 16. * angle_to_vec(rot)], 1.5, color)?;
+                        graphics::draw(ctx, &mesh, DrawParam::default())?;
+                    }
+                }
             }
             enemy.draw(ctx, &s.assets, WHITE)?;
+            // Small grenades below the enemy for each one it carries
+            for n in 0..enemy.pl.wep.utilities.grenades {
+                let drawparams = DrawParam::new()
+                    .dest(enemy.pl.obj.pos + Vector2::new(-12. + 8. * f32::from(n), 18.))
+                    .offset(Point2::new(0.5, 0.5))
+                    .scale(Vector2::new(0.5, 0.5));
+                let img = s.assets.get_img(ctx, "weapons/pineapple");
+                graphics::draw(ctx, &*img, drawparams)?;
+            }
         }
         for (i, decal) in self.level.decals.iter().enumerate() {
             if let Tool::Selector(Selection{ref decals, ..})= self.current {
@@ -331,6 +399,24 @@ impl GameState for Editor {
             }
             decal.draw(ctx, &s.assets, WHITE)?;
         }
+        for (i, door) in self.level.doors.iter().enumerate() {
+            if let Tool::Selector(Selection{ref doors, ..})= self.current {
+                if doors.contains(&i) {
+                    let mesh = Mesh::new_circle(ctx, DrawMode::fill(), door.obj.pos, 17., 0.5, YELLOW)?;
+                    graphics::draw(ctx, &mesh, DrawParam::default())?;
+                }
+            }
+            door.draw(ctx, &s.assets, WHITE)?;
+        }
+        for (i, alarm) in self.level.alarms.iter().enumerate() {
+            if let Tool::Selector(Selection{ref alarms, ..}) = self.current {
+                if alarms.contains(&i) {
+                    let mesh = Mesh::new_circle(ctx, DrawMode::fill(), alarm.obj.pos, 17., 0.5, YELLOW)?;
+                    graphics::draw(ctx, &mesh, DrawParam::default())?;
+                }
+            }
+            alarm.draw(ctx, &s.assets, WHITE)?;
+        }
 
         // Draw init pick-up-ables on top of enemies so they're visible
         for (i, pickup) in self.level.pickups.iter().enumerate() {
@@ -358,6 +444,15 @@ impl GameState for Editor {
             graphics::draw(ctx, &*img, drawparams)?;
         }
 
+        if let Some(ref diagnostics) = self.diagnostics {
+            for d in diagnostics {
+                if let Some(pos) = d.pos {
+                    let mesh = Mesh::new_circle(ctx, DrawMode::stroke(2.), pos, 18., 0.5, RED)?;
+                    graphics::draw(ctx, &mesh, DrawParam::default())?;
+                }
+            }
+        }
+
         // Draw moving objects shadows
         if let Tool::Selector(ref selection @ Selection{moving: Some(_), ..}) = self.current {
             let mousepos = self.mousepos(s);
@@ -383,6 +478,16 @@ impl GameState for Editor {
                 dec.obj.pos += dist;
                 dec.draw(ctx, &s.assets, TRANS)?;
             }
+            for &i in &selection.doors {
+                let mut door = self.level.doors[i].clone();
+                door.obj.pos = snap_to_tile(door.obj.pos + dist);
+                door.draw(ctx, &s.assets, TRANS)?;
+            }
+            for &i in &selection.alarms {
+                let mut alarm = self.level.alarms[i].clone();
+                alarm.obj.pos += dist;
+                alarm.draw(ctx, &s.assets, TRANS)?;
+            }
             for &i in &selection.pickups {
                 let pickup = self.level.pickups[i];
                 let drawparams = graphics::DrawParam {
@@ -423,7 +528,7 @@ impl GameState for Editor {
     fn draw_hud(&mut self, s: &State, ctx: &mut Context) -> GameResult<()> {
         let dest = (self.mousepos(s) + s.offset).into();
         match self.current {
-            Tool::Selector(_) => (),
+            Tool::Selector(_) | Tool::Patrol(_) => (),
             Tool::Inserter(Insertion::Material(_)) => (),
             Tool::Inserter(Insertion::Pickup(index)) => {
                 let drawparams = graphics::DrawParam {
@@ -447,7 +552,7 @@ impl GameState for Editor {
                 let img = s.assets.get_img(ctx, &WEAPONS[id].entity_sprite);
                 graphics::draw(ctx, &*img, drawparams)?;
             }
-            Tool::Inserter(Insertion::Enemy{rot}) => {
+            Tool::Inserter(Insertion::Enemy{rot, kind}) => {
                 let drawparams = graphics::DrawParam {
                     dest,
                     rotation: rot,
@@ -455,7 +560,7 @@ impl GameState for Editor {
                     color: TRANS,
                     .. Default::default()
                 };
-                let img = s.assets.get_img(ctx, "common/enemy");
+                let img = s.assets.get_img(ctx, &ARCHETYPES[kind].sprite);
                 graphics::draw(ctx, &*img, drawparams)?;
             }
             Tool::Inserter(Insertion::Decal{spr, rot}) => {
@@ -469,6 +574,28 @@ impl GameState for Editor {
                 let img = s.assets.get_img(ctx, spr);
                 graphics::draw(ctx, &*img, drawparams)?;
             }
+            Tool::Inserter(Insertion::Alarm{rot}) => {
+                let drawparams = graphics::DrawParam {
+                    dest,
+                    rotation: rot,
+                    offset: Point2::new(0.5, 0.5).into(),
+                    color: TRANS,
+                    .. Default::default()
+                };
+                let img = s.assets.get_img(ctx, "common/alarm");
+                graphics::draw(ctx, &*img, drawparams)?;
+            }
+            Tool::Inserter(Insertion::Door{rot}) => {
+                let drawparams = graphics::DrawParam {
+                    dest: (snap_to_tile(self.mousepos(s)) + s.offset).into(),
+                    rotation: rot,
+                    offset: Point2::new(0.5, 0.5).into(),
+                    color: TRANS,
+                    .. Default::default()
+                };
+                let img = s.assets.get_img(ctx, "common/door");
+                graphics::draw(ctx, &*img, drawparams)?;
+            }
             Tool::Inserter(Insertion::Exit) => {
                 let drawparams = graphics::DrawParam {
                     dest,
@@ -507,6 +634,10 @@ impl GameState for Editor {
         self.entities_bar.draw(ctx, s, if let Tool::Inserter(ins) = self.current{Some(ins)}else{None})?;
         self.extra_bar.draw(ctx, s, if let Tool::Inserter(ins) = self.current{Some(ins)}else{None})?;
 
+        if self.diagnostics.is_some() {
+            self.diagnostics_text.draw_text(ctx)?;
+        }
+
         self.mat_text.draw_text(ctx)?;
         self.entities_bar.ent_text.draw_text(ctx)?;
         self.extra_bar.ent_text.draw_text(ctx)
@@ -522,10 +653,31 @@ impl GameState for Editor {
             Key(X) => self.level = Level::load(&self.save).unwrap(),
             Key(C) => self.draw_visibility_cones.toggle(),
             Key(G) => self.snap_on_grid.toggle(),
+            Key(V) => if self.diagnostics.take().is_none() {
+                self.revalidate();
+            },
             Key(P) => {
-                s.switch(StateSwitch::Play(self.level.clone()));
+                s.switch(StateSwitch::PlayTest(self.level.clone()));
             }
             Key(T) => self.current = Tool::Selector(Selection::default()),
+            Key(R) => match self.current {
+                Tool::Selector(Selection{ref enemies, ..}) if enemies.len() == 1 => self.current = Tool::Patrol(enemies[0]),
+                Tool::Patrol(i) => self.current = Tool::Selector(Selection{enemies: vec![i], .. Default::default()}),
+                _ => (),
+            },
+            Key(Minus) | Key(Equals) => if let Some(waypoint) = self.last_waypoint() {
+                let change = if let Key(Minus) = event { -0.5 } else { 0.5 };
+                waypoint.wait = (waypoint.wait + change).max(0.);
+            } else if let Tool::Selector(Selection{ref enemies, ..}) = self.current {
+                // Change how many grenades the selected enemies carry
+                for &i in enemies {
+                    let grenades = &mut self.level.enemies[i].pl.wep.utilities.grenades;
+                    *grenades = if let Key(Minus) = event { grenades.saturating_sub(1) } else { grenades.saturating_add(1).min(9) };
+                }
+            },
+            Key(Delete) | Key(Back) if self.last_waypoint().is_some() => if let Tool::Patrol(i) = self.current {
+                self.level.enemies[i].patrol.route.pop();
+            },
             Key(Delete) | Key(Back) => if let Tool::Selector(ref mut selection) = self.current {
                 #[allow(clippy::unneeded_field_pattern)]
                 let Selection {
@@ -534,6 +686,8 @@ impl GameState for Editor {
                     mut pickups,
                     mut weapons,
                     mut decals,
+                    mut doors,
+                    mut alarms,
                     exit, moving: _,
                 } = ::std::mem::replace(selection, Selection::default());
 
@@ -552,6 +706,14 @@ impl GameState for Editor {
                 for decal in decals.into_iter().rev() {
                     self.level.decals.remove(decal);
                 }
+                doors.sort();
+                for door in doors.into_iter().rev() {
+                    self.level.doors.remove(door);
+                }
+                alarms.sort();
+                for alarm in alarms.into_iter().rev() {
+                    self.level.alarms.remove(alarm);
+                }
                 pickups.sort();
                 for pickup in pickups.into_iter().rev() {
                     self.level.pickups.remove(pickup);
@@ -563,20 +725,30 @@ impl GameState for Editor {
             }
             Key(Comma) => {
                 self.rotation_speed = 0.;
-                if shift {
+                if let Some(waypoint) = self.last_waypoint() {
+                    waypoint.facing = Some(waypoint.facing.unwrap_or(0.) - std::f32::consts::FRAC_PI_4);
+                } else if let Tool::Inserter(Insertion::Door{ref mut rot}) = self.current {
+                    *rot -= std::f32::consts::FRAC_PI_2;
+                } else if shift {
                     match self.current {
-                        Tool::Inserter(Insertion::Enemy{ref mut rot}) => *rot -= std::f32::consts::FRAC_PI_4,
+                        Tool::Inserter(Insertion::Enemy{ref mut rot, ..}) => *rot -= std::f32::consts::FRAC_PI_4,
                         Tool::Inserter(Insertion::Decal{ref mut rot, ..}) => *rot -= std::f32::consts::FRAC_PI_4,
+                        Tool::Inserter(Insertion::Alarm{ref mut rot}) => *rot -= std::f32::consts::FRAC_PI_4,
                         _ => (),
                     }
                 }
             }
             Key(Period) => {
                 self.rotation_speed = 0.;
-                if shift {
+                if let Some(waypoint) = self.last_waypoint() {
+                    waypoint.facing = Some(waypoint.facing.unwrap_or(0.) + std::f32::consts::FRAC_PI_4);
+                } else if let Tool::Inserter(Insertion::Door{ref mut rot}) = self.current {
+                    *rot += std::f32::consts::FRAC_PI_2;
+                } else if shift {
                     match self.current {
-                        Tool::Inserter(Insertion::Enemy{ref mut rot}) => *rot += std::f32::consts::FRAC_PI_4,
+                        Tool::Inserter(Insertion::Enemy{ref mut rot, ..}) => *rot += std::f32::consts::FRAC_PI_4,
                         Tool::Inserter(Insertion::Decal{ref mut rot, ..}) => *rot += std::f32::consts::FRAC_PI_4,
+                        Tool::Inserter(Insertion::Alarm{ref mut rot}) => *rot += std::f32::consts::FRAC_PI_4,
                         _ => (),
                     }
                 }
@@ -589,6 +761,9 @@ impl GameState for Editor {
             Mouse(Mb::Left) => self.click(s, ctx),
             _ => (),
         }
+        if self.diagnostics.is_some() {
+            self.revalidate();
+        }
     }
     fn event_down(&mut self, s: &mut State, ctx: &mut Context, event: Event) {
         use self::KeyCode::*;
@@ -613,6 +788,16 @@ impl GameState for Editor {
                         return selection.moving = Some(mousepos);
                     }
                 }
+                for &i in &selection.doors {
+                    if (self.level.doors[i].obj.pos - mousepos).norm() <= 16. {
+                        return selection.moving = Some(mousepos);
+                    }
+                }
+                for &i in &selection.alarms {
+                    if (self.level.alarms[i].obj.pos - mousepos).norm() <= 16. {
+                        return selection.moving = Some(mousepos);
+                    }
+                }
                 for &i in &selection.pickups {
                     if (self.level.pickups[i].0 - mousepos).norm() <= 16. {
                         return selection.moving = Some(mousepos);
@@ -639,6 +824,13 @@ impl GameState for Editor {
 }
 
 impl Editor {
+    /// The waypoint most recently added to the route being edited
+    fn last_waypoint(&mut self) -> Option<&mut Waypoint> {
+        match self.current {
+            Tool::Patrol(i) => self.level.enemies.get_mut(i).and_then(|e| e.patrol.route.last_mut()),
+            _ => None,
+        }
+    }
     fn click(&mut self, s: &mut State, ctx: &mut Context) {
         let mousepos = self.mousepos(&s);
 
@@ -675,6 +867,13 @@ impl Editor {
                         for i in selection.decals.iter().rev() {
                             self.level.decals[*i].obj.pos += dist;
                         }
+                        for i in selection.doors.iter().rev() {
+                            let door = &mut self.level.doors[*i];
+                            door.obj.pos = snap_to_tile(door.obj.pos + dist);
+                        }
+                        for i in selection.alarms.iter().rev() {
+                            self.level.alarms[*i].obj.pos += dist;
+                        }
                         for i in selection.pickups.iter().rev() {
                             self.level.pickups[*i].0 += dist;
                         }
@@ -710,6 +909,18 @@ impl Editor {
                                 return
                             }
                         }
+                        for (i, door) in self.level.doors.iter().enumerate() {
+                            if (door.obj.pos - mousepos).norm() <= 16. && !selection.doors.contains(&i) {
+                                selection.doors.push(i);
+                                return
+                            }
+                        }
+                        for (i, alarm) in self.level.alarms.iter().enumerate() {
+                            if (alarm.obj.pos - mousepos).norm() <= 16. && !selection.alarms.contains(&i) {
+                                selection.alarms.push(i);
+                                return
+                            }
+                        }
                         for (i, &pickup) in self.level.pickups.iter().enumerate() {
                             if (pickup.0 - mousepos).norm() <= 16. && !selection.pickups.contains(&i) {
                                 selection.pickups.push(i);
@@ -724,18 +935,35 @@ impl Editor {
                         }
                     }
                 }
+                Tool::Patrol(i) => if let Some(enemy) = self.level.enemies.get_mut(i) {
+                    enemy.patrol.route.push(Waypoint::new(mousepos));
+                },
                 Tool::Inserter(Insertion::Exit) => {
                     self.level.exit = Some(self.mousepos(&s));
                     self.current = Tool::Selector(Selection{exit: true, .. Default::default()});
                 }
-                Tool::Inserter(Insertion::Enemy{rot}) => {
+                Tool::Inserter(Insertion::Enemy{rot, kind}) => {
                     s.mplayer.play(ctx, "reload").unwrap();
-                    self.level.enemies.push(Enemy::new(Object::with_rot(mousepos, rot)));
-                    self.level.weapons.push(WEAPONS["glock"].make_drop(mousepos));
+                    let kind = &ARCHETYPES[kind];
+                    let mut enemy = Enemy::new(Object::with_rot(mousepos, rot), kind);
+                    enemy.pl.wep.utilities.grenades = kind.grenades;
+                    self.level.enemies.push(enemy);
+                    if let Some(wep) = kind.preferred_weapon {
+                        self.level.weapons.push(wep.make_drop(mousepos));
+                    }
                 },
                 Tool::Inserter(Insertion::Decal{spr, rot}) => {
                     self.level.decals.push(Decal::new(Object::with_rot(mousepos, rot), spr));
                 }
+                Tool::Inserter(Insertion::Door{rot}) => {
+                    let (x, y) = Grid::snap(mousepos);
+                    if self.level.doors.iter().all(|door| door.tile() != (x, y)) {
+                        self.level.doors.push(Door::new(x, y, rot));
+                    }
+                }
+                Tool::Inserter(Insertion::Alarm{rot}) => {
+                    self.level.alarms.push(Alarm::new(Object::with_rot(mousepos, rot)));
+                }
                 Tool::Inserter(Insertion::Pickup(i)) => {
                     self.level.pickups.push((mousepos, i));
                 },
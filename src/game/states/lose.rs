@@ -8,6 +8,7 @@ use crate::{
     game::{
         DELTA,
         State, Content, GameState, StateSwitch, world::{Statistics, Level},
+        replay::Replay,
         event::{Event::{self, *}, MouseButton as Mb, KeyCode as Key}
     },
 };
@@ -23,9 +24,11 @@ pub struct Lose {
     enemies_text: PosText,
     restart_btn: Button<()>,
     edit_btn: Option<Button<()>>,
+    replay_btn: Button<()>,
     level: Level,
     health: Health,
-    weapon: WepSlots
+    weapon: WepSlots,
+    replay: Replay,
 }
 
 impl Lose {
@@ -44,6 +47,8 @@ impl Lose {
         } else {
             None
         };
+        let replay_y = if edit_btn.is_some() { 200. } else { 132. };
+        let replay_btn = Button::new(ctx, &s.assets, Rect{x: 3. * w / 7., y: replay_y, w: w / 7., h: 64.}, "Watch replay", ())?;
 
         Ok(Box::new(Lose {
             you_died,
@@ -51,9 +56,11 @@ impl Lose {
             enemies_text,
             restart_btn,
             edit_btn,
+            replay_btn,
             level: stats.level,
             health: stats.health_left,
             weapon: stats.weapon,
+            replay: stats.replay,
         }))
     }
     fn edit(&self, s: &mut State) {
@@ -62,6 +69,9 @@ impl Lose {
     fn restart(&self, s: &mut State) {
         s.switch(StateSwitch::PlayWith{lvl: Box::new(self.level.clone()), health: self.health, wep: self.weapon.clone()})
     }
+    fn watch_replay(&self, s: &mut State) {
+        s.switch(StateSwitch::Replay{lvl: Box::new(self.level.clone()), replay: Box::new(self.replay.clone())})
+    }
 }
 
 impl GameState for Lose {
@@ -70,6 +80,7 @@ impl GameState for Lose {
         if let Some(btn) = &self.edit_btn {
             btn.draw(ctx)?;
         }
+        self.replay_btn.draw(ctx)?;
 
         self.you_died.draw_center(ctx)?;
         self.time_text.draw_text(ctx)?;
@@ -87,9 +98,15 @@ impl GameState for Lose {
                         self.edit(s);
                     }
                 }
+                if self.replay_btn.in_bounds(s.mouse) {
+                    self.watch_replay(s);
+                }
             } 
             _ => (),
         }
 
     }
+    fn get_replay(&self) -> Option<&Replay> {
+        Some(&self.replay)
+    }
 }
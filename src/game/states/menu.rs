@@ -26,6 +26,8 @@ enum Callback {
     SwitchPlay(PathBuf),
     SwitchEditor,
     Campaign(PathBuf),
+    /// Goes to the next difficulty
+    Difficulty,
 }
 
 // ↓
@@ -45,7 +47,7 @@ impl Menu {
         };
         s.mplayer.play(ctx, "music")?;
 
-        let buttons = match &mut s.content {
+        let mut buttons = match &mut s.content {
             Content::Campaign(_cam) => {
                 unreachable!()
             }
@@ -71,6 +73,8 @@ impl Menu {
                     .collect()
             },
         };
+        let rect = button_rect(w, buttons.len() as f32);
+        buttons.push(Button::new(ctx, &s.assets, rect, &difficulty_text(s), Callback::Difficulty)?);
 
         Ok(Box::new(Menu {
             title_txt: s.assets.text_sized(Point2::new(w / 2., 16.), 32.).and_text("Main Menu"),
@@ -80,6 +84,10 @@ impl Menu {
     }
 }
 
+fn difficulty_text(s: &State) -> String {
+    format!("Difficulty: {}", s.difficulty.name)
+}
+
 impl GameState for Menu {
     fn draw_hud(&mut self, _s: &State, ctx: &mut Context) -> GameResult<()> {
         self.title_txt.draw_center(ctx)?;
@@ -101,8 +109,14 @@ impl GameState for Menu {
     // }
     fn event_up(&mut self, s: &mut State, ctx: &mut Context, event: Event) {
         if let Mouse(MouseButton::Left) = event {
-            for button in &self.buttons {
+            for (i, button) in self.buttons.iter().enumerate() {
                 if button.in_bounds(s.mouse) {
+                    if let Callback::Difficulty = button.callback {
+                        s.difficulty = s.difficulty.next();
+                        let rect = button_rect(s.width, i as f32);
+                        self.buttons[i] = Button::new(ctx, &s.assets, rect, &difficulty_text(s), Callback::Difficulty).unwrap();
+                        return;
+                    }
                     s.mplayer.stop(ctx, "music").unwrap();
                     match &button.callback {
                         Callback::Campaign(cam) => {
@@ -116,6 +130,7 @@ impl GameState for Menu {
                             s.switch(StateSwitch::Play(lvl));
                         },
                         Callback::SwitchEditor => s.switch(StateSwitch::Editor(None)),
+                        Callback::Difficulty => unreachable!(),
                     }
                 }
             }
@@ -1,4 +1,3 @@
-use std::f32::consts::FRAC_1_SQRT_2 as COS_45_D;
 use crate::{
     ext::FloatExt,
     util::{
@@ -8,9 +7,10 @@ use crate::{
         Vector2, Point2
     },
     io::tex::PosText,
-    obj::{Object, bullet::Bullet, decal::Decal, pickup::Pickup, player::{Player, WepSlots, ActiveSlot}, enemy::{Enemy, Chaser}, health::Health, weapon::{self, WeaponInstance}, grenade::GrenadeUpdate},
+    obj::{Object, decal::Decal, effect::Effect, player::{Player, WepSlots, ActiveSlot}, health::Health, weapon::WeaponInstance},
     game::{
-        DELTA, State, GameState, StateSwitch, world::{Level, Statistics, World},
+        State, GameState, StateSwitch, world::{Level, Statistics, World, GameEvent, Input, Action},
+        replay::{Replay, Playhead},
         event::{Event::{self, Key, Mouse}, MouseButton, KeyCode, KeyMods}
     },
 };
@@ -28,9 +28,10 @@ use ggez::{
     },
 };
 
-use rand::{thread_rng, prelude::SliceRandom};
+use std::collections::VecDeque;
+use rand::{Rng, thread_rng, prelude::SliceRandom};
 
-pub fn new_blood(mut obj: Object) -> Decal {
+pub fn new_blood<R: Rng>(mut obj: Object, rng: &mut R) -> Decal {
     obj.pos += 16. * angle_to_vec(obj.rot);
     Decal {
         obj,
@@ -40,10 +41,13 @@ pub fn new_blood(mut obj: Object) -> Decal {
             "common/blood2",
             "common/blood3",
             "common/blood3",
-        ].choose(&mut thread_rng()).copied().map(Into::into).unwrap(),
+        ].choose(rng).copied().map(Into::into).unwrap(),
     }
 }
 
+/// How many casings are left lying around before the oldest ones disappear
+const MAX_CASINGS: usize = 200;
+
 /// The state of the game
 pub struct Play {
     hp_text: PosText,
@@ -54,71 +58,181 @@ pub struct Play {
     hud: Hud,
     world: World,
     holes: SpriteBatch,
-    cur_pickup: Option<usize>,
-    victory_time: f32,
-    time: usize,
+    effects: Vec<Effect>,
+    /// Casings lying on the ground, oldest first
+    casings: VecDeque<Decal>,
+    input: Input,
+    mode: Mode,
     initial: (Health, WepSlots),
     level: Level,
+    /// Whether to show what the enemies are doing
+    ai_overlay: bool,
+}
+
+/// Where the player's input comes from
+enum Mode {
+    /// Input is read from the keyboard and mouse and recorded
+    Record(Replay),
+    /// Input is read from a replay
+    Playback {
+        replay: Replay,
+        head: Playhead,
+    },
+}
+
+impl Mode {
+    fn replay(&self) -> &Replay {
+        match self {
+            Mode::Record(replay) | Mode::Playback{replay, ..} => replay,
+        }
+    }
 }
 
 impl Play {
     #[allow(clippy::new_ret_no_self)]
     pub fn new(ctx: &mut Context, s: &mut State, level: Level, pl: Option<(Health, WepSlots)>) -> GameResult<Box<dyn GameState>> {
+        let seed = thread_rng().gen();
+        let replay = Replay::new(&level, seed, pl.as_ref(), s.difficulty);
+
+        Self::with_mode(ctx, s, level, pl, Mode::Record(replay), false)
+    }
+    /// Plays the level showing what the enemies are doing
+    pub fn play_test(ctx: &mut Context, s: &mut State, level: Level) -> GameResult<Box<dyn GameState>> {
+        let seed = thread_rng().gen();
+        let replay = Replay::new(&level, seed, None, s.difficulty);
+
+        Self::with_mode(ctx, s, level, None, Mode::Record(replay), true)
+    }
+    /// Plays back a recorded play session of the level
+    pub fn replay(ctx: &mut Context, s: &mut State, level: Level, replay: Replay) -> GameResult<Box<dyn GameState>> {
+        if level.checksum() != replay.level_hash {
+            warn!("Replay was recorded on a different version of the level");
+        }
+        let pl = replay.loadout.as_ref().map(|l| l.restore());
+
+        Self::with_mode(ctx, s, level, pl, Mode::Playback{replay, head: Playhead::default()}, false)
+    }
+    fn with_mode(ctx: &mut Context, s: &mut State, level: Level, pl: Option<(Health, WepSlots)>, mode: Mode, ai_overlay: bool) -> GameResult<Box<dyn GameState>> {
         mouse::set_cursor_hidden(ctx, true);
 
-        let mut player = Player::from_point(level.start_point.unwrap_or_else(|| Point2::new(500., 500.)));
-        if let Some((h, w)) = pl {
-            player = player.with_health(h).with_weapon(w);
-        };
+        let world = World::new(level.clone(), pl, mode.replay().seed, mode.replay().difficulty);
 
         Ok(Box::new(
             Play {
-                level: level.clone(),
-                initial: (player.health, player.wep.clone()),
+                level,
+                initial: (world.player.health, world.player.wep.clone()),
                 hp_text: s.assets.text(Point2::new(4., 4.)).and_text("100"),
                 arm_text: s.assets.text(Point2::new(4., 33.)).and_text("100"),
                 reload_text: s.assets.text(Point2::new(4., 62.)).and_text("0.0").and_text("s"),
                 wep_text: WeaponInstance::weapon_text(Point2::new(2., 87.), &s.assets),
                 status_text: s.assets.text(Point2::new(s.width as f32 / 2., s.height as f32 / 2. + 32.)).and_text(""),
                 hud: Hud::new(ctx)?,
-                time: 0,
-                victory_time: 0.,
-                cur_pickup: None,
-                world: {
-                    let mut world = World {
-                        enemies: level.enemies,
-                        bullets: Vec::new(),
-                        grenades: Vec::new(),
-                        weapons: level.weapons,
-                        player,
-                        palette: level.palette,
-                        grid: level.grid,
-                        exit: level.exit,
-                        intels: level.intels,
-                        decals: level.decals,
-                        pickups: level.pickups.into_iter().map(|(p, i)| Pickup::new(p, i)).collect(),
-                    };
-                    world.enemy_pickup();
-                    world.player_pickup();
-
-                    if world.player.wep.get_active().is_none() {
-                        warn!("player has no weapon");
-                    }
-
-                    for enemy_pos in world.enemies.iter().filter_map(|enemy| if enemy.pl.wep.get_active().is_none() {Some(enemy.pl.obj.pos)}else{None}) {
-                        warn!("enemy at {:.2} has no weapon", enemy_pos)
-                    }
-
-                    world
-                },
+                input: Input::default(),
+                mode,
+                world,
                 holes: SpriteBatch::new(s.assets.get_img(ctx, "common/hole").clone()),
+                effects: Vec::new(),
+                casings: VecDeque::new(),
+                ai_overlay,
             }
         ))
     }
+    fn handle_event(&mut self, s: &mut State, ctx: &mut Context, event: GameEvent) -> GameResult<()> {
+        match event {
+            GameEvent::Sound(snd) => s.mplayer.play(ctx, snd)?,
+            GameEvent::Blood(obj) => {
+                // Only for show, so it stays out of the simulation's random numbers
+                let blood = new_blood(obj, &mut thread_rng());
+                self.world.decals.push(blood);
+            }
+            GameEvent::BulletHole(obj) => {
+                self.holes.add(obj.drawparams());
+            }
+            GameEvent::Shot(muzzle, weapon) => {
+                if let Some(spr) = weapon.muzzle_flash {
+                    self.effects.push(Effect::muzzle_flash(&muzzle, spr));
+                }
+                if let Some(spr) = weapon.casing {
+                    self.effects.push(Effect::casing(&muzzle, spr, &mut thread_rng()));
+                }
+            }
+            GameEvent::Death => s.switch(StateSwitch::Lose(Box::new(Statistics{
+                time: self.world.time,
+                enemies_left: self.world.enemies.len(),
+                health_left: self.initial.0,
+                level: self.level.clone(),
+                weapon: self.initial.1.clone(),
+                replay: self.mode.replay().clone(),
+                undetected: !self.world.detected,
+            }))),
+            GameEvent::Victory | GameEvent::Noise(_) => (),
+            GameEvent::Win => s.switch(StateSwitch::Win(Box::new(Statistics{
+                level: self.level.clone(),
+                time: self.world.time,
+                enemies_left: self.world.enemies.len(),
+                health_left: self.world.player.health,
+                weapon: self.world.player.wep.clone(),
+                replay: self.mode.replay().clone(),
+                undetected: !self.world.detected,
+            }))),
+        }
+        Ok(())
+    }
+    /// Draws what each enemy can see, where it's going and what it's doing
+    fn draw_ai(&self, s: &State, ctx: &mut Context) -> GameResult<()> {
+        for enemy in &self.world.enemies {
+            let pos = enemy.pl.obj.pos;
+            enemy.draw_visibility_cone(ctx)?;
+
+            if !enemy.path.is_empty() {
+                let mut path = enemy.path.clone();
+                path.push(pos);
+                let mesh = Mesh::new_polyline(ctx, DrawMode::stroke(1.), &path, GREEN)?;
+                graphics::draw(ctx, &mesh, DrawParam::default())?;
+            }
+
+            s.assets.text_sized(pos + Vector2::new(-16., 18.), 12.)
+                .and_text(enemy.behaviour.name())
+                .and_text(format!(" {:.0}%", enemy.awareness * 100.))
+                .draw_text(ctx)?;
+        }
+        Ok(())
+    }
+    /// Draws a marker around the player pointing towards each enemy that's noticing them,
+    /// going from yellow to red as the enemy gets closer to spotting them
+    fn draw_detection(&self, s: &State, ctx: &mut Context) -> GameResult<()> {
+        let player_pos = self.world.player.obj.pos;
+        let centre = player_pos + s.offset;
+
+        let mut builder = MeshBuilder::new();
+        let mut noticed = false;
+        for enemy in &self.world.enemies {
+            let noticing = enemy.awareness > 0. && enemy.awareness < 1.;
+            if !noticing && enemy.spotted <= 0. {
+                continue;
+            }
+            let dist = enemy.pl.obj.pos - player_pos;
+            if dist.norm_squared() == 0. {
+                continue;
+            }
+            let rot = angle_from_vec(dist);
+            let tip = centre + DETECTION_MARKER_DISTANCE * angle_to_vec(rot);
+            let left = centre + (DETECTION_MARKER_DISTANCE - 12.) * angle_to_vec(rot - 0.2);
+            let right = centre + (DETECTION_MARKER_DISTANCE - 12.) * angle_to_vec(rot + 0.2);
+            let color = Color{r: 1., g: 1. - enemy.awareness, b: 0., a: 0.3 + 0.7 * enemy.awareness};
+
+            builder.polygon(DrawMode::fill(), &[tip, left, right], color)?;
+            noticed = true;
+        }
+        if noticed {
+            let mesh = builder.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
+        Ok(())
+    }
 }
 
 impl GameState for Play {
-    #[allow(clippy::cognitive_complexity)]
     fn update(&mut self, s: &mut State, ctx: &mut Context) -> GameResult<()> {
         self.hp_text.update(0, format!("{:02.0}", self.world.player.health.hp))?;
         self.arm_text.update(0, format!("{:02.0}", self.world.player.health.armour))?;
@@ -126,229 +240,53 @@ impl GameState for Play {
             self.reload_text.update(0, format!("{:.1}", wep.loading_time))?;
             wep.update_text(&mut self.wep_text)?;
         }
-        if let Some(i) = self.cur_pickup {
+        if let Some(i) = self.world.weapon_in_reach() {
             // TODO change text to say what's being swapped out
             self.status_text.text.fragments_mut()[0]= format!("Press F to pick up {}", self.world.weapons[i]).into();
+        } else if self.world.dragging.is_some() {
+            self.status_text.update(0, "Press C to let go of body")?;
+        } else if self.world.corpse_in_reach().is_some() {
+            self.status_text.update(0, "Press C to drag body")?;
+        } else if let Some(i) = self.world.door_in_reach(self.world.player.obj.pos) {
+            self.status_text.update(0, if self.world.doors[i].open { "Press E to close door" } else { "Press E to open door" })?;
         } else {
             self.status_text.update(0, "")?;
         }
 
-        let mut deads = Vec::new();
-        for (i, grenade) in self.world.grenades.iter_mut().enumerate().rev() {
-            let g_update = grenade.update(ctx, &s.assets, &self.world.palette, &self.world.grid, &mut self.world.player, &mut *self.world.enemies)?;
-
-            match g_update {
-                GrenadeUpdate::Explosion{player_hit, enemy_hits} => {
-                    s.mplayer.play(ctx, "boom")?;
-
-                    if player_hit {
-                        self.world.decals.push(new_blood(self.world.player.obj.clone()));
-                        s.mplayer.play(ctx, "hit")?;
-
-                        if self.world.player.health.is_dead() {
-                            s.switch(StateSwitch::Lose(Box::new(Statistics{
-                                time: self.time,
-                                enemies_left: self.world.enemies.len(),
-                                health_left: self.initial.0,
-                                level: self.level.clone(),
-                                weapon: self.initial.1.clone(),
-                            })));
-                            s.mplayer.play(ctx, "death")?;
-                        } else {
-                            s.mplayer.play(ctx, "hurt")?;
-                        }
-                    }
-                    for i in enemy_hits {
-                        let enemy = &self.world.enemies[i];
-                        s.mplayer.play(ctx, "hit")?;
-
-                        self.world.decals.push(new_blood(enemy.pl.obj.clone()));
-                        if enemy.pl.health.is_dead() {
-                            s.mplayer.play(ctx, "death")?;
-
-                            let Enemy{pl: Player{wep, obj: Object{pos, ..}, ..}, ..}
-                                = self.world.enemies.remove(i);
-                            for wep in wep {
-                                self.world.weapons.push(wep.into_drop(pos));
-                            }
-                        } else {
-                            if !enemy.behaviour.chasing() {
-                                self.world.enemies[i].behaviour = Chaser::LookAround{
-                                    dir: grenade.obj.pos - enemy.pl.obj.pos
-                                };
-                            }
-                            s.mplayer.play(ctx, "hurt")?;
-                        }
-                    }
-                }
-                GrenadeUpdate::Dead => {
-                    deads.push(i);
-                }
-                GrenadeUpdate::None => (),
+        match self.mode {
+            Mode::Record(ref mut replay) => {
+                self.input.movement = Vector2::new(hor(&ctx), ver(&ctx));
+                self.input.walking = keyboard::is_mod_active(ctx, KeyMods::SHIFT);
+                self.input.firing = mouse::button_pressed(ctx, MouseButton::Left) || keyboard::is_key_pressed(ctx, KeyCode::Space);
+                replay.push(&self.input);
             }
-        }
-        for i in deads {
-            self.world.grenades.remove(i);
-        }
-
-        let mut deads = Vec::new();
-        for (i, bullet) in self.world.bullets.iter_mut().enumerate().rev() {
-            let hit = bullet.update(&self.world.palette, &self.world.grid, &mut self.world.player, &mut *self.world.enemies);
-            
-            use crate::obj::bullet::Hit;
-
-            match hit {
-                Hit::None => (),
-                Hit::Wall => {
-                    s.mplayer.play(ctx, &bullet.weapon.impact_snd)?;
-                    let dir = angle_to_vec(bullet.obj.rot);
-                    bullet.obj.pos += Vector2::new(5.*dir.x.signum(), 5.*dir.y.signum());
-                    self.holes.add(bullet.obj.drawparams());
-                    deads.push(i);
-                }
-                Hit::Player => {
-                    deads.push(i);
-                    self.world.decals.push(new_blood(bullet.obj.clone()));
-                    s.mplayer.play(ctx, "hit")?;
-
-                    if self.world.player.health.is_dead() {
-                        s.switch(StateSwitch::Lose(Box::new(Statistics{
-                            time: self.time,
-                            enemies_left: self.world.enemies.len(),
-                            health_left: self.initial.0,
-                            level: self.level.clone(),
-                            weapon: self.initial.1.clone(),
-                        })));
-                        s.mplayer.play(ctx, "death")?;
-                    } else {
-                        s.mplayer.play(ctx, "hurt")?;
-                    }
-                }
-                Hit::Enemy(e) => {
-                    deads.push(i);
-                    let enemy = &self.world.enemies[e];
-                    s.mplayer.play(ctx, "hit")?;
-
-                    self.world.decals.push(new_blood(bullet.obj.clone()));
-                    if enemy.pl.health.is_dead() {
-                        s.mplayer.play(ctx, "death")?;
-
-                        let Enemy{pl: Player{wep, obj: Object{pos, ..}, ..}, ..}
-                            = self.world.enemies.remove(e);
-                        for wep in wep {
-                            self.world.weapons.push(wep.into_drop(pos));
-                        }
-                    } else {
-                        if !enemy.behaviour.chasing() {
-                            self.world.enemies[e].behaviour = Chaser::LookAround{
-                                dir: bullet.obj.pos - enemy.pl.obj.pos
-                            };
-                        }
-                        s.mplayer.play(ctx, "hurt")?;
-                    }
+            Mode::Playback{ref replay, ref mut head} => {
+                if let Some(input) = replay.next_input(head) {
+                    self.input = input.clone();
+                } else {
+                    // The recording stopped before the level was won or lost
+                    s.switch(StateSwitch::Menu);
+                    return Ok(());
                 }
             }
         }
-        for i in deads {
-            self.world.bullets.remove(i);
-        }
 
-        let mut deads = Vec::new();
-        for (i, &intel) in self.world.intels.iter().enumerate().rev() {
-            if (intel-self.world.player.obj.pos).norm() <= 15. {
-                deads.push(i);
-                s.mplayer.play(ctx, "hit")?;
-            }
-        }
-        for i in deads {
-            self.world.intels.remove(i);
-        }
-        let mut deads = Vec::new();
-        for (i, pickup) in self.world.pickups.iter().enumerate().rev() {
-            if (pickup.pos-self.world.player.obj.pos).norm() <= 15. && pickup.apply(&mut self.world.player.health) {
-                deads.push(i);
-                s.mplayer.play(ctx, "hit")?;
-            }
-        }
-        for i in deads {
-            self.world.pickups.remove(i);
-        }
-        self.cur_pickup = None;
-        for (i, weapon) in self.world.weapons.iter().enumerate().rev() {
-            if (weapon.pos-self.world.player.obj.pos).norm() <= 29. {
-                self.cur_pickup = Some(i);
-                break
-            }
-        }
-
-        // Define player velocity here already because enemies need it
-        let player_vel = Vector2::new(hor(&ctx), ver(&ctx));
-
-        for enemy in self.world.enemies.iter_mut() {
-            if enemy.can_see(self.world.player.obj.pos, &self.world.palette, &self.world.grid) {
-                enemy.behaviour = Chaser::LastKnown{
-                    pos: self.world.player.obj.pos,
-                    vel: player_vel,
-                };
-
-                if let Some(wep) = enemy.pl.wep.get_active_mut() {
-                    if let Some(bm) = wep.shoot(ctx, &mut s.mplayer)? {
-                        let pos = enemy.pl.obj.pos + 20. * angle_to_vec(enemy.pl.obj.rot);
-                        let mut bul = Object::new(pos);
-                        bul.rot = enemy.pl.obj.rot;
+        let events = self.world.step(&self.input);
+        self.input.actions.clear();
 
-                        for bullet in bm.make(bul) {
-                            self.world.bullets.push(bullet);
-                        }
-                    }
-                }
-            }
-            enemy.update(ctx, &mut s.mplayer)?;
+        for event in events {
+            self.handle_event(s, ctx, event)?;
         }
-
-        let speed = if !keyboard::is_mod_active(ctx, KeyMods::SHIFT) {
-            200.
-        } else {
-            100.
-        };
-        if let Some(wep) = self.world.player.wep.get_active_mut() {
-            wep.update(ctx, &mut s.mplayer)?;
-            if wep.cur_clip > 0 && mouse::button_pressed(ctx, MouseButton::Left) && wep.weapon.fire_mode.is_auto() {
-                if let Some(bm) = wep.shoot(ctx, &mut s.mplayer)? {
-                    let pos = self.world.player.obj.pos + 20. * angle_to_vec(self.world.player.obj.rot);
-                    let mut bul = Object::new(pos);
-                    bul.rot = self.world.player.obj.rot;
-
-                    for bullet in bm.make(bul) {
-                        self.world.bullets.push(bullet);
+        for i in (0..self.effects.len()).rev() {
+            if self.effects[i].update(&self.world.palette, &self.world.grid) {
+                if let Some(decal) = self.effects.remove(i).into_decal() {
+                    if self.casings.len() >= MAX_CASINGS {
+                        self.casings.pop_front();
                     }
+                    self.casings.push_back(decal);
                 }
             }
         }
-        self.world.player.obj.move_on_grid(player_vel, speed, &self.world.palette, &self.world.grid);
-
-        let game_won = match self.world.exit {
-            Some(p) => self.world.intels.is_empty() && (p - self.world.player.obj.pos).norm() < 32.,
-            None => self.world.enemies.is_empty(),
-        };
-
-        if game_won && self.victory_time <= 0. {
-            s.mplayer.play(ctx, "victory")?;
-            self.victory_time += DELTA;
-        } else if self.victory_time > 0. {
-            self.victory_time += DELTA;
-        } else {
-            self.time += 1;
-        }
-        if self.victory_time >= 2. {
-            s.switch(StateSwitch::Win(Box::new(Statistics{
-                level: self.level.clone(),
-                time: self.time,
-                enemies_left: self.world.enemies.len(),
-                health_left: self.world.player.health,
-                weapon: self.world.player.wep.clone(),
-            })));
-        }
         Ok(())
     }
     fn logic(&mut self, s: &mut State, ctx: &mut Context) -> GameResult<()> {
@@ -356,7 +294,9 @@ impl GameState for Play {
 
         self.hud.update_bars(ctx, &self.world.player)?;
 
-        self.world.player.obj.rot = angle_from_vec(dist);
+        if let Mode::Record(_) = self.mode {
+            self.input.aim = angle_from_vec(dist);
+        }
 
         // Center the camera on the player
         let p = self.world.player.obj.pos;
@@ -381,6 +321,21 @@ impl GameState for Play {
         for decal in &self.world.decals {
             decal.draw(ctx, &s.assets, WHITE)?;
         }
+        for casing in &self.casings {
+            casing.draw(ctx, &s.assets, WHITE)?;
+        }
+        for corpse in &self.world.corpses {
+            corpse.draw(ctx, &s.assets)?;
+        }
+        for door in &self.world.doors {
+            door.draw(ctx, &s.assets, WHITE)?;
+        }
+        for alarm in &self.world.alarms {
+            alarm.draw(ctx, &s.assets, WHITE)?;
+        }
+        for drop in &self.world.grenade_drops {
+            drop.draw(ctx, &s.assets)?;
+        }
 
         for pickup in &self.world.pickups {
             let drawparams = graphics::DrawParam {
@@ -406,9 +361,24 @@ impl GameState for Play {
         for enemy in &self.world.enemies {
             enemy.draw(ctx, &s.assets, WHITE)?;
         }
+        if self.ai_overlay {
+            self.draw_ai(s, ctx)?;
+        }
+        let mut tracers = MeshBuilder::new();
+        let mut traced = false;
+        for bullet in &self.world.bullets {
+            traced |= bullet.add_tracer(&mut tracers)?;
+        }
+        if traced {
+            let mesh = tracers.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
         for bullet in &self.world.bullets {
             bullet.draw(ctx, &s.assets)?;
         }
+        for effect in &self.effects {
+            effect.draw(ctx, &s.assets)?;
+        }
         for grenade in &self.world.grenades {
             grenade.draw(ctx, &s.assets)?;
         }
@@ -423,10 +393,11 @@ impl GameState for Play {
         self.reload_text.draw_text(ctx)?;
         self.wep_text.draw_text(ctx)?;
         self.status_text.draw_center(ctx)?;
+        self.draw_detection(s, ctx)?;
 
         {
             let drawparams = DrawParam::from(([104., 2.],));
-            let img = s.assets.get_img(ctx, "weapons/knife");
+            let img = s.assets.get_img(ctx, &self.world.player.wep.melee.weapon.entity_sprite);
             graphics::draw(ctx, &*img, drawparams)?;
         }
         if let Some(holster_wep) = &self.world.player.wep.holster {
@@ -456,90 +427,25 @@ impl GameState for Play {
         let img = s.assets.get_img(ctx, "common/crosshair");
         graphics::draw(ctx, &*img, drawparams)
     }
-    fn event_up(&mut self, s: &mut State, ctx: &mut Context, event: Event) {
+    fn event_up(&mut self, _s: &mut State, _ctx: &mut Context, event: Event) {
         use self::KeyCode::*;
-        match event {
-            Key(Q) | Key(Key0) | Key(Numpad0) => self.world.player.wep.switch(ActiveSlot::Knife),
-            Key(Key1) | Key(Numpad1) => self.world.player.wep.switch(ActiveSlot::Holster),
-            Key(Key2) | Key(Numpad2) => self.world.player.wep.switch(ActiveSlot::Holster2),
-            Key(Key3) | Key(Numpad3) => self.world.player.wep.switch(ActiveSlot::Sling),
-            Key(G) => {
-                if let Some(wep) = self.world.player.wep.take_active() {
-                    self.world.weapons.push(wep.into_drop(self.world.player.obj.pos));
-                } else {
-                    warn!("Dropped nothing");
-                }
-            }
-            Key(R) => {
-                if let Some(wep) = self.world.player.wep.get_active_mut() {
-                    wep.reload(ctx, &mut s.mplayer).unwrap()
-                } else {
-                    let weapon = &weapon::WEAPONS["glock"];
-                    self.world.bullets.push(Bullet{obj: self.world.player.obj.clone(), vel: Vector2::new(weapon.bullet_speed, 0.), weapon});
-                }
-            },
-            Key(F) => {
-                if let Some(i) = self.cur_pickup {
-                    if let Some(new_drop) = self.world.player.wep.add_weapon(WeaponInstance::from_drop(self.world.weapons.remove(i))) {
-                        self.world.weapons.push(new_drop.into_drop(self.world.player.obj.pos));
-                    }
-                    self.cur_pickup = None;
-                }
-            },
-            Mouse(MouseButton::Left) | Key(Space) => {
-                if let Some(wep) = self.world.player.wep.get_active_mut() {
-                    if let Some(bm) = wep.shoot(ctx, &mut s.mplayer).unwrap() {
-                        let pos = self.world.player.obj.pos + 20. * angle_to_vec(self.world.player.obj.rot);
-                        let mut bul = Object::new(pos);
-                        bul.rot = self.world.player.obj.rot;
-
-                        for bullet in bm.make(bul) {
-                            self.world.bullets.push(bullet);
-                        }
-                    }
-                } else {
-                    // TODO do knives with bullets too
-                    let player = &mut self.world.player;
-                    let mut backstab = false;
-                    let mut dead = None;
-
-                    for (i, enemy) in self.world.enemies.iter_mut().enumerate() {
-                        let dist = player.obj.pos-enemy.pl.obj.pos;
-                        let dist_len = dist.norm();
-                        if dist_len < 44. {
-                            backstab = angle_to_vec(enemy.pl.obj.rot).dot(&dist) / dist_len < COS_45_D;
-
-                            self.world.decals.push(new_blood(enemy.pl.obj.clone()));
-                            enemy.pl.health.weapon_damage(if backstab { 165. } else { 33. }, 0.92);
-                            if enemy.pl.health.is_dead() {
-                                dead = Some(i);
-                                break;
-                            }
-                        }
-                    }
-                    if let Some(i) = dead {
-                        s.mplayer.play(ctx, "death").unwrap();
-
-                        let Enemy{pl: Player{wep, obj: Object{pos, ..}, ..}, ..}
-                            = self.world.enemies.remove(i);
-                        for wep in wep {
-                            self.world.weapons.push(wep.into_drop(pos));
-                        }
-                    }
-
-                    s.mplayer.play(ctx, if backstab {"shuk"} else {"hling"}).unwrap();
-                }
-            }
-            Mouse(MouseButton::Right) => {
-                if let Some(gm) = self.world.player.wep.utilities.throw_grenade(ctx, &mut s.mplayer).unwrap() {
-                    let pos = self.world.player.obj.pos + 20. * angle_to_vec(self.world.player.obj.rot);
-                    let mut gren = Object::new(pos);
-                    gren.rot = self.world.player.obj.rot;
-
-                    self.world.grenades.push(gm.make(gren));
-                }
-            }
-            _ => (),
+        let action = match event {
+            Key(Q) | Key(Key0) | Key(Numpad0) => Action::Switch(ActiveSlot::Knife),
+            Key(Key1) | Key(Numpad1) => Action::Switch(ActiveSlot::Holster),
+            Key(Key2) | Key(Numpad2) => Action::Switch(ActiveSlot::Holster2),
+            Key(Key3) | Key(Numpad3) => Action::Switch(ActiveSlot::Sling),
+            Key(G) => Action::Drop,
+            Key(R) => Action::Reload,
+            Key(F) => Action::PickUp,
+            Key(E) => Action::Interact,
+            Key(C) => Action::Drag,
+            Key(B) => Action::SwitchFireMode,
+            Mouse(MouseButton::Left) | Key(Space) => Action::Shoot,
+            Mouse(MouseButton::Right) => Action::ThrowGrenade,
+            _ => return,
+        };
+        if let Mode::Record(_) = self.mode {
+            self.input.actions.push(action);
         }
     }
 
@@ -549,8 +455,14 @@ impl GameState for Play {
     fn get_mut_world(&mut self) -> Option<&mut World> {
         Some(&mut self.world)
     }
+    fn get_replay(&self) -> Option<&Replay> {
+        Some(self.mode.replay())
+    }
 }
 
+/// How far from the player the markers for enemies noticing them are drawn
+const DETECTION_MARKER_DISTANCE: f32 = 48.;
+
 #[derive(Debug)]
 pub struct Hud {
     hud_bar: Mesh,
@@ -8,6 +8,7 @@ use crate::{
     game::{
         DELTA,
         State, Content, GameState, StateSwitch, world::{Level, Statistics},
+        replay::Replay,
         event::{Event::{self, Key, Mouse}, MouseButton, KeyCode},
     }
 };
@@ -33,10 +34,13 @@ pub struct Win {
     time_text: PosText,
     enemies_text: PosText,
     health_text: PosText,
+    stealth_text: PosText,
     buttons: WinButtons,
+    replay_btn: Button<()>,
     health: Health,
     level: Level,
     weapon: WepSlots,
+    replay: Replay,
 }
 
 impl Win {
@@ -49,6 +53,9 @@ impl Win {
         let enemy_total = stats.level.enemies.len();
         let enemies_text = s.assets.text(Point2::new(4., 36.)).and_text(format!("Enemies killed: {} / {}", enemy_total - stats.enemies_left, enemy_total));
         let health_text = s.assets.text(Point2::new(4., 52.)).and_text(format!("Health left: {:02.0} / {:02.0}", stats.health_left.hp, stats.health_left.armour));
+        let stealth_text = s.assets.text(Point2::new(4., 68.)).and_text(if stats.undetected { "Undetected" } else { "Detected" });
+        let replay_y = if let Content::File(_) = s.content { 200. } else { 132. };
+        let replay_btn = Button::new(ctx, &s.assets, Rect{x: 3. * w / 7., y: replay_y, w: w / 7., h: 64.}, "Watch replay", ())?;
 
         Ok(Box::new(Win {
             buttons: {
@@ -66,9 +73,12 @@ impl Win {
             time_text,
             enemies_text,
             health_text,
+            stealth_text,
+            replay_btn,
             level: stats.level,
             health: stats.health_left,
             weapon: stats.weapon,
+            replay: stats.replay,
         }))
     }
     fn restart(&self, s: &mut State) {
@@ -77,6 +87,9 @@ impl Win {
     fn edit(&self, s: &mut State) {
         s.switch(StateSwitch::Editor(Some(self.level.clone())));
     }
+    fn watch_replay(&self, s: &mut State) {
+        s.switch(StateSwitch::Replay{lvl: Box::new(self.level.clone()), replay: Box::new(self.replay.clone())})
+    }
     fn continue_play(&self, s: &mut State) {
         let lvl;
         match &mut s.content {
@@ -105,11 +118,13 @@ impl GameState for Win {
                 continue_btn.draw(ctx)?;
             }
         }
+        self.replay_btn.draw(ctx)?;
 
         self.level_complete.draw_center(ctx)?;
         self.time_text.draw_text(ctx)?;
         self.enemies_text.draw_text(ctx)?;
-        self.health_text.draw_text(ctx)
+        self.health_text.draw_text(ctx)?;
+        self.stealth_text.draw_text(ctx)
     }
     fn event_up(&mut self, s: &mut State, _ctx: &mut Context, event: Event) {
         use self::KeyCode::*;
@@ -132,5 +147,13 @@ impl GameState for Win {
             }
             _ => (),
         }
+        if let Mouse(MouseButton::Left) = event {
+            if self.replay_btn.in_bounds(s.mouse) {
+                self.watch_replay(s)
+            }
+        }
+    }
+    fn get_replay(&self) -> Option<&Replay> {
+        Some(&self.replay)
     }
 }
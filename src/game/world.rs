@@ -1,30 +1,47 @@
 use crate::{
-    util::{Point2, Vector2, sstr},
-    io::tex::{Assets, },
+    util::{Point2, Vector2},
+    io::{tex::{Assets, }, save::fnv1a},
     obj::{
         player::{Player, WepSlots},
         enemy::Enemy,
         health::Health,
         bullet::Bullet,
-        grenade::Grenade,
-        weapon::{WeaponInstance, WeaponDrop, WEAPONS},
+        grenade::{Grenade, GrenadeDrop},
+        weapon::{WeaponInstance, WeaponDrop},
         pickup::Pickup,
-        decal::{Decal, OldDecoration},
-    }
-};
-use ggez::{
-    Context, GameResult,
-    error::GameError,
+        decal::Decal,
+        door::Door,
+        alarm::Alarm,
+        corpse::Corpse,
+    },
+    game::{replay::Replay, difficulty::Difficulty},
 };
+use ggez::{Context, GameResult};
 
 use std::path::Path;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{Write, BufRead, BufReader};
+use std::io::{Read, Write};
 
-use bincode;
+use rand::{SeedableRng, rngs::StdRng};
 
 mod material;
 pub use material::*;
+mod pathfinding;
+pub use pathfinding::*;
+mod sim;
+pub use sim::*;
+mod format;
+pub use format::{LevelError, LEVEL_VERSION, format_version};
+use format::{read_level, write_level};
+mod text;
+use text::{level_from_text, level_to_text};
+mod validate;
+pub use validate::*;
+mod noise;
+pub use noise::*;
+mod cover;
+pub use cover::*;
 
 #[derive(Debug)]
 /// All the objects in the current world
@@ -37,12 +54,84 @@ pub struct World {
     pub enemies: Vec<Enemy>,
     pub bullets: Vec<Bullet<'static>>,
     pub grenades: Vec<Grenade>,
+    /// Grenades dropped by dead enemies
+    pub grenade_drops: Vec<GrenadeDrop>,
     pub weapons: Vec<WeaponDrop<'static>>,
     pub decals: Vec<Decal>,
     pub pickups: Vec<Pickup>,
+    pub doors: Vec<Door>,
+    pub alarms: Vec<Alarm>,
+    /// Bodies of killed enemies
+    pub corpses: Vec<Corpse>,
+    /// The index of the body the player is dragging
+    pub dragging: Option<usize>,
+    /// Whether any enemy has noticed the player
+    pub detected: bool,
+    /// Ticks spent before completing the level
+    pub time: usize,
+    /// Seconds since the level was completed
+    pub victory_time: f32,
+    /// Seconds since the player last fired a shot
+    pub since_shot: f32,
+    /// Source of all randomness in the world so it can be replayed
+    pub rng: StdRng,
+    pub difficulty: &'static Difficulty,
 }
 
 impl World {
+    /// Sets up a world to play the given level with an optional health and set of weapons for the player
+    pub fn new(level: Level, pl: Option<(Health, WepSlots)>, seed: u64, difficulty: &'static Difficulty) -> Self {
+        let mut player = Player::from_point(level.start_point.unwrap_or_else(|| Point2::new(500., 500.)))
+            .with_damage_taken(difficulty.damage_taken);
+        if let Some((h, w)) = pl {
+            player = player.with_health(h).with_weapon(w);
+        } else {
+            player = player.with_health(difficulty.health);
+        }
+
+        let mut world = World {
+            enemies: level.enemies,
+            bullets: Vec::new(),
+            grenades: Vec::new(),
+            grenade_drops: Vec::new(),
+            weapons: level.weapons,
+            player,
+            palette: level.palette,
+            grid: level.grid,
+            exit: level.exit,
+            intels: level.intels,
+            decals: level.decals,
+            pickups: level.pickups.into_iter().map(|(p, i)| Pickup::new(p, i)).collect(),
+            doors: level.doors,
+            alarms: level.alarms,
+            corpses: Vec::new(),
+            dragging: None,
+            detected: false,
+            time: 0,
+            victory_time: 0.,
+            since_shot: f32::INFINITY,
+            rng: StdRng::seed_from_u64(seed),
+            difficulty,
+        };
+        for enemy in &mut world.enemies {
+            enemy.pl.health = enemy.kind.health;
+        }
+        for door in &world.doors {
+            world.grid.set_door(door.tile(), !door.open);
+        }
+        world.enemy_pickup();
+        world.player_pickup();
+
+        if world.player.wep.get_active().is_none() {
+            warn!("player has no weapon");
+        }
+
+        for enemy_pos in world.enemies.iter().filter_map(|enemy| if enemy.pl.wep.get_active().is_none() {Some(enemy.pl.obj.pos)}else{None}) {
+            warn!("enemy at {:.2} has no weapon", enemy_pos)
+        }
+
+        world
+    }
     pub fn enemy_pickup(&mut self) {
         for enemy in &mut self.enemies {
             let mut dead = None;
@@ -53,10 +142,9 @@ impl World {
                 }
             }
             if let Some(i) = dead {
-                let weapon_slot = enemy.pl.wep.insert(&self.weapons[i].weapon);
-
-                if weapon_slot.is_none() {
-                    *weapon_slot = Some(WeaponInstance::from_drop(self.weapons.remove(i)));
+                if enemy.pl.wep.has_room_for(&self.weapons[i].weapon) {
+                    // Nothing worth keeping gets swapped out when there's room
+                    let _default = enemy.pl.wep.add_weapon(WeaponInstance::from_drop(self.weapons.remove(i)));
                 }
             }
             let mut deads = Vec::new();
@@ -84,10 +172,9 @@ impl World {
                 }
             }
             for i in deads {
-                let weapon_slot = player.wep.insert(&self.weapons[i].weapon);
-
-                if weapon_slot.is_none() {
-                    *weapon_slot = Some(WeaponInstance::from_drop(self.weapons.remove(i)));
+                if player.wep.has_room_for(&self.weapons[i].weapon) {
+                    // Nothing worth keeping gets swapped out when there's room
+                    let _default = player.wep.add_weapon(WeaponInstance::from_drop(self.weapons.remove(i)));
                 }
             }
         }
@@ -113,6 +200,9 @@ pub struct Statistics {
     pub health_left: Health,
     pub level: Level,
     pub weapon: WepSlots,
+    pub replay: Replay,
+    /// Whether no enemy ever noticed the player
+    pub undetected: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -126,6 +216,41 @@ pub struct Level {
     pub pickups: Vec<(Point2, u8)>,
     pub decals: Vec<Decal>,
     pub weapons: Vec<WeaponDrop<'static>>,
+    /// Weapons whose ids aren't known, kept so they can be reported and saved back as they were
+    pub unknown_weapons: Vec<(Point2, String)>,
+    pub doors: Vec<Door>,
+    pub alarms: Vec<Alarm>,
+}
+
+impl PartialEq for Level {
+    /// Levels are equal when they hold the same things, so anything that isn't saved with a level is ignored
+    fn eq(&self, other: &Self) -> bool {
+        fn same_enemy(a: &Enemy, b: &Enemy) -> bool {
+            a.pl.obj == b.pl.obj
+                && a.pl.health == b.pl.health
+                && a.pl.wep.utilities.grenades == b.pl.wep.utilities.grenades
+                && a.kind.id == b.kind.id
+                && a.patrol.route == b.patrol.route
+        }
+        fn same_weapon(a: &WeaponDrop, b: &WeaponDrop) -> bool {
+            a.pos == b.pos && a.cur_clip == b.cur_clip && a.ammo == b.ammo && a.weapon.id == b.weapon.id
+        }
+
+        self.palette == other.palette
+            && self.grid == other.grid
+            && self.start_point == other.start_point
+            && self.enemies.len() == other.enemies.len()
+            && self.enemies.iter().zip(&other.enemies).all(|(a, b)| same_enemy(a, b))
+            && self.exit == other.exit
+            && self.intels == other.intels
+            && self.pickups == other.pickups
+            && self.decals == other.decals
+            && self.weapons.len() == other.weapons.len()
+            && self.weapons.iter().zip(&other.weapons).all(|(a, b)| same_weapon(a, b))
+            && self.unknown_weapons == other.unknown_weapons
+            && self.doors == other.doors
+            && self.alarms == other.alarms
+    }
 }
 
 impl Level {
@@ -140,128 +265,77 @@ impl Level {
             pickups: Vec::new(),
             decals: Vec::new(),
             weapons: Vec::new(),
+            unknown_weapons: Vec::new(),
+            doors: Vec::new(),
+            alarms: Vec::new(),
         }
     }
+    /// Loads a level from a file, as text if it has the `toml` extension
     pub fn load<P: AsRef<Path>>(path: P) -> GameResult<Self> {
-        let mut reader = BufReader::new(File::open(path)?);
-        let mut ret = Level::new(Palette::default(), 0, 0);
-
-        // For support of older level files
-        const WEAPONS_OLD: [&str; 6] = [
-            "glock",
-            "five_seven",
-            "magnum",
-            "m4a1",
-            "ak47",
-            "arwp",
-        ];
-
-        loop {
-            let mut buf = String::with_capacity(16);
-            reader.read_line(&mut buf)?;
-            match &*buf.trim_end() {
-                "" => continue,
-                "PALETTE" => ret.palette = bincode::deserialize_from(&mut reader)
-                    .map(|mats: Vec<Box<str>>| Palette::new(mats.into_iter().map(sstr).collect()))
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "GRD" => ret.grid = bincode::deserialize_from(&mut reader)
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "GRID" => {
-                    let (w, grid): (usize, Vec<u16>) = bincode::deserialize_from(&mut reader)
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-                    ret.grid = Grid {
-                        mats: grid.into_iter().map(|n| n as u8).collect(),
-                        width: w as u16
-                    }
-                }
-                "START" => ret.start_point = Some(
-                    bincode::deserialize_from(&mut reader)
-                        .map(|(x, y)| Point2::new(x, y))
-                        .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?
-                ),
-                "ENEMIES" => ret.enemies = bincode::deserialize_from(&mut reader)
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "POINT GOAL" => ret.exit = Some(bincode::deserialize_from(&mut reader)
-                    .map(|(x, y)| Point2::new(x, y))
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?),
-                "INTELS" => ret.intels = bincode::deserialize_from(&mut reader)
-                    .map(|l: Vec<(f32, f32)>| l.into_iter().map(|(x, y)| Point2::new(x, y)).collect())
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "DECORATIONS" => ret.decals = bincode::deserialize_from(&mut reader)
-                    .map(|old_decs: Vec<OldDecoration>| old_decs.into_iter().map(|od| od.renew()).collect())
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "DECS" => ret.decals = bincode::deserialize_from(&mut reader)
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "PICKUPS" => ret.pickups = bincode::deserialize_from(&mut reader)
-                    .map(|l: Vec<((f32, f32), u8)>| l.into_iter().map(|((x, y), i)| (Point2::new(x, y), i)).collect())
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "WEPS" => ret.weapons = bincode::deserialize_from(&mut reader)
-                    .map(|l: Vec<((f32, f32), String)>| l.into_iter().map(|((x, y), id)| WEAPONS[&*id].make_drop(Point2::new(x, y))).collect())
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "WEAPONS" => ret.weapons = bincode::deserialize_from(&mut reader)
-                    .map(|l: Vec<((f32, f32), u8)>| l.into_iter().map(|((x, y), i)| WEAPONS[WEAPONS_OLD[i as usize]].make_drop(Point2::new(x, y))).collect())
-                    .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?,
-                "END" => break, 
-                _ => return Err(GameError::ResourceLoadError("Bad section".to_string()))
-            }
+        if is_text(path.as_ref()) {
+            return Self::load_text(path);
         }
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        Ok(Self::from_bytes(&bytes)?)
+    }
+    /// Loads a level from its text representation
+    pub fn load_text<P: AsRef<Path>>(path: P) -> GameResult<Self> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
 
-        Ok(ret)
+        Ok(level_from_text(&s)?)
     }
+    /// Reads a level from the contents of a level file of any version
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LevelError> {
+        read_level(bytes)
+    }
+    /// Saves the level to a file, as text if it has the `toml` extension
     pub fn save<P: AsRef<Path>>(&self, path: P) -> GameResult<()> {
-        let mut file = File::create(path)?;
-
-        writeln!(file, "GRD")?;
-        bincode::serialize_into(&mut file, &self.grid)
-            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        if let Some(start) = self.start_point {
-            writeln!(file, "\nSTART")?;
-            bincode::serialize_into(&mut file, &(start.x, start.y))
-            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-        if !self.enemies.is_empty() {
-            writeln!(file, "\nENEMIES")?;
-            bincode::serialize_into(&mut file, &self.enemies)
-            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-        if let Some(p) = self.exit {
-            writeln!(file, "\nPOINT GOAL")?;
-            bincode::serialize_into(&mut file, &(p.x, p.y))
-            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-        if !self.intels.is_empty() {
-            writeln!(file, "\nINTELS")?;
-            let intels: Vec<_> = self.intels.iter().map(|p| (p.x, p.y)).collect();
-            bincode::serialize_into(&mut file, &intels)
-                .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-        if !self.decals.is_empty() {
-            writeln!(file, "\nDECS")?;
-            bincode::serialize_into(&mut file, &self.decals)
-            .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-        if !self.pickups.is_empty() {
-            writeln!(file, "\nPICKUPS")?;
-            let pickups: Vec<_> = self.pickups.iter().map(|&(p, i)| ((p.x, p.y), i)).collect();
-            bincode::serialize_into(&mut file, &pickups)
-                .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-        if !self.weapons.is_empty() {
-            writeln!(file, "\nWEPS")?;
-            let pickups: Vec<((f32, f32), &'static str)> = self.weapons.iter().map(|w| ((w.pos.x, w.pos.y), w.weapon.id)).collect();
-            bincode::serialize_into(&mut file, &pickups)
-                .map_err(|e| GameError::ResourceLoadError(format!("{:?}", e)))?;
-        }
-
-        writeln!(file, "\nEND")?;
+        if is_text(path.as_ref()) {
+            return self.save_text(path);
+        }
+        self.write(File::create(path)?)
+    }
+    /// Saves the level in its text representation
+    pub fn save_text<P: AsRef<Path>>(&self, path: P) -> GameResult<()> {
+        let s = level_to_text(self)?;
+        File::create(path)?.write_all(s.as_bytes())?;
         Ok(())
     }
+    /// The level in its text representation
+    pub fn to_text(&self) -> Result<String, LevelError> {
+        level_to_text(self)
+    }
+    /// Reads a level from its text representation
+    pub fn from_text(s: &str) -> Result<Self, LevelError> {
+        level_from_text(s)
+    }
+    /// Checksum of the level's contents for recognising it later
+    pub fn checksum(&self) -> u64 {
+        let mut buf = Vec::new();
+        self.write(&mut buf).expect("writing to memory");
+        fnv1a(&buf)
+    }
+    /// Writes the level in the current version of the level format
+    pub fn write<W: Write>(&self, file: W) -> GameResult<()> {
+        Ok(write_level(self, file)?)
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Whether a level file is in the text format
+fn is_text(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "toml").unwrap_or(false)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Grid{
     width: u16,
     mats: Vec<u8>,
+    /// Tiles blocked by a closed door
+    #[serde(skip)]
+    closed_doors: HashSet<(u16, u16)>,
 }
 
 impl Grid {
@@ -269,6 +343,7 @@ impl Grid {
         Grid {
             width,
             mats: vec![0; (width*height) as usize],
+            closed_doors: HashSet::new(),
         }
     }
     pub fn migrate(&mut self, from: &Palette, to: Palette) -> Palette {
@@ -349,8 +424,24 @@ impl Grid {
         self.is_solid(pal, x, y)
     }
     pub fn is_solid(&self, pal: &Palette, x: u16, y: u16) -> bool {
+        self.is_wall(pal, x, y) || self.closed_doors.contains(&(x, y))
+    }
+    /// Whether the tile's material is solid regardless of any doors in it
+    pub fn is_wall(&self, pal: &Palette, x: u16, y: u16) -> bool {
         self.get(x, y).map(|m| pal.is_solid(m)).unwrap_or(true)
     }
+    /// Marks a tile as blocked or unblocked by a door
+    pub fn set_door(&mut self, (x, y): (u16, u16), closed: bool) {
+        if closed {
+            self.closed_doors.insert((x, y));
+        } else {
+            self.closed_doors.remove(&(x, y));
+        }
+    }
+    #[inline]
+    pub fn is_closed_door(&self, x: u16, y: u16) -> bool {
+        self.closed_doors.contains(&(x, y))
+    }
     pub fn insert(&mut self, x: u16, y: u16, mat: u8) {
         if x < self.width {
             let i = self.idx(x, y);
@@ -376,7 +467,7 @@ impl Grid {
             let mat = self.get(gx, gy);
 
             if let Some(mat) = mat {
-                if pal.is_solid(mat) {
+                if pal.is_solid(mat) || self.is_closed_door(gx, gy) {
                     break RayCast::n_half(cur, dest-cur, to_wall);
                 }
                 if cur.x < 0. || cur.y < 0. {
@@ -0,0 +1,81 @@
+use crate::util::Point2;
+use super::{Grid, Palette, tile_centre};
+
+/// How close to a threat cover can be and still be worth hiding behind
+const MIN_THREAT_DISTANCE: f32 = 96.;
+
+/// Tiles next to a cover tile to step out to when peeking, straight ones first
+const PEEK_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// A place next to a wall hidden from a threat
+#[derive(Debug, Clone, Copy)]
+pub struct Cover {
+    pub pos: Point2,
+    /// Where to step out to in order to shoot at the threat
+    pub peek: Point2,
+}
+
+impl Grid {
+    /// Finds the closest cover within `radius` of `from` that's hidden from `threat`
+    ///
+    /// Cover has to be next to a solid tile and have a tile next to it with a clear view of the threat to peek out from.
+    pub fn find_cover(&self, pal: &Palette, from: Point2, threat: Point2, radius: f32) -> Option<Cover> {
+        let (fx, fy) = Grid::snap(from);
+        let r = (radius / 32.).ceil() as i32;
+
+        let mut best: Option<(f32, Cover)> = None;
+        for y in i32::from(fy) - r..=i32::from(fy) + r {
+            for x in i32::from(fx) - r..=i32::from(fx) + r {
+                let tile = match self.open_tile(pal, x, y) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let pos = tile_centre(tile.0, tile.1);
+                let dist = (pos - from).norm();
+                if dist > radius || best.map(|(d, _)| dist >= d).unwrap_or(false) {
+                    continue;
+                }
+                if (threat - pos).norm() < MIN_THREAT_DISTANCE {
+                    continue;
+                }
+                let next_to_wall = [(1, 0), (-1, 0), (0, 1), (0, -1)].iter()
+                    .any(|&(dx, dy)| self.open_tile(pal, x + dx, y + dy).is_none());
+                if !next_to_wall || self.ray_cast(pal, pos, threat - pos, true).full() {
+                    continue;
+                }
+
+                let peek = PEEK_OFFSETS.iter()
+                    .filter_map(|&(dx, dy)| self.open_tile(pal, x + dx, y + dy))
+                    .map(|(px, py)| tile_centre(px, py))
+                    .find(|&peek| self.ray_cast(pal, pos, peek - pos, true).full()
+                        && self.ray_cast(pal, peek, threat - peek, true).full());
+                if let Some(peek) = peek {
+                    best = Some((dist, Cover{pos, peek}));
+                }
+            }
+        }
+
+        best.map(|(_, cover)| cover)
+    }
+    /// The tile at the given coordinates if it's in the grid and not solid
+    fn open_tile(&self, pal: &Palette, x: i32, y: i32) -> Option<(u16, u16)> {
+        if x < 0 || y < 0 || x >= i32::from(self.width()) || y >= i32::from(self.height()) {
+            return None;
+        }
+        let (x, y) = (x as u16, y as u16);
+        if self.is_solid(pal, x, y) {
+            None
+        } else {
+            Some((x, y))
+        }
+    }
+}
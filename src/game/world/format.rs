@@ -0,0 +1,595 @@
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    util::{Point2, sstr},
+    io::save::fnv1a,
+    obj::{
+        Object,
+        enemy::{Enemy, Archetype, Patrol, Waypoint},
+        decal::{Decal, OldDecoration},
+        door::Door,
+        weapon::WEAPONS,
+    },
+};
+use ggez::error::GameError;
+
+use super::{Level, Grid, Palette};
+
+/// What every level file in the sectioned format starts with
+const MAGIC: &[u8; 8] = b"TOPSKUD\n";
+/// Version of the level format that gets written
+///
+/// Files without the magic header are treated as version 0.
+pub const LEVEL_VERSION: u16 = 2;
+
+/// How many materials a palette can have for tiles to be able to refer to all of them
+const MAX_MATERIALS: usize = std::u8::MAX as usize + 1;
+
+/// Upgrades the sections of a level file from one version to the next,
+/// the migration at index `n` goes from version `n` to `n + 1`
+type Migration = fn(&mut Vec<Section>) -> Result<(), LevelError>;
+
+const MIGRATIONS: [Migration; LEVEL_VERSION as usize] = [
+    v0_to_v1,
+    v1_to_v2,
+];
+
+/// Something that went wrong reading or writing a level file
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    /// The file was made by a newer version of the game
+    UnsupportedVersion(u16),
+    /// A section of a legacy file that can't be skipped because its length isn't known
+    UnknownSection {
+        name: String,
+        offset: usize,
+    },
+    /// The contents of a section couldn't be read or written
+    BadSection {
+        name: String,
+        offset: usize,
+        error: String,
+    },
+    /// A section every level needs wasn't found before the end of the file at `offset`
+    MissingSection {
+        name: String,
+        offset: usize,
+    },
+    /// The file ended in the middle of the section starting at `offset`
+    Truncated {
+        offset: usize,
+    },
+    /// The file has been changed since it was written
+    ChecksumMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// The text representation of a level couldn't be read or written
+    Text(String),
+}
+
+impl Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::LevelError::*;
+        match self {
+            Io(e) => write!(f, "{}", e),
+            UnsupportedVersion(v) => write!(f, "Level format version {} is newer than the supported version {}", v, LEVEL_VERSION),
+            UnknownSection{name, offset} => write!(f, "Unknown section `{}' at byte {}", name, offset),
+            BadSection{name, offset, error} => write!(f, "Bad section `{}' at byte {}: {}", name, offset, error),
+            MissingSection{name, offset} => write!(f, "No section `{}' before the end of the file at byte {}", name, offset),
+            Truncated{offset} => write!(f, "File ends in the middle of the section at byte {}", offset),
+            ChecksumMismatch{expected, found} => write!(f, "Checksum is {:016x} but should be {:016x}", found, expected),
+            Text(e) => write!(f, "Bad level text: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<io::Error> for LevelError {
+    fn from(e: io::Error) -> Self {
+        LevelError::Io(e)
+    }
+}
+
+impl From<LevelError> for GameError {
+    fn from(e: LevelError) -> Self {
+        match e {
+            LevelError::Io(e) => e.into(),
+            e => GameError::ResourceLoadError(e.to_string()),
+        }
+    }
+}
+
+/// The raw contents of a section of a level file
+#[derive(Debug)]
+struct Section {
+    name: String,
+    /// Where in the file the section starts
+    offset: usize,
+    data: Vec<u8>,
+}
+
+impl Section {
+    fn new<T: Serialize + ?Sized>(name: &str, offset: usize, val: &T) -> Result<Self, LevelError> {
+        Ok(Section {
+            name: name.to_owned(),
+            offset,
+            data: bincode::serialize(val).map_err(|e| LevelError::BadSection {
+                name: name.to_owned(),
+                offset,
+                error: e.to_string(),
+            })?,
+        })
+    }
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, LevelError> {
+        bincode::deserialize(&self.data).map_err(|e| self.error(e))
+    }
+    fn error<E: Display>(&self, error: E) -> LevelError {
+        LevelError::BadSection {
+            name: self.name.clone(),
+            offset: self.offset,
+            error: error.to_string(),
+        }
+    }
+}
+
+/// The version of the level format the contents of a file are in
+pub fn format_version(bytes: &[u8]) -> u16 {
+    if bytes.starts_with(MAGIC) && bytes.len() >= MAGIC.len() + 2 {
+        u16::from_le_bytes([bytes[MAGIC.len()], bytes[MAGIC.len() + 1]])
+    } else {
+        0
+    }
+}
+
+/// Reads a level from the contents of a file in any version of the format
+pub fn read_level(bytes: &[u8]) -> Result<Level, LevelError> {
+    let (version, mut sections) = if bytes.starts_with(MAGIC) {
+        read_sections(bytes)?
+    } else {
+        (0, read_legacy_sections(bytes)?)
+    };
+
+    if version > LEVEL_VERSION {
+        return Err(LevelError::UnsupportedVersion(version));
+    }
+    for migrate in &MIGRATIONS[version as usize..] {
+        migrate(&mut sections)?;
+    }
+
+    let mut ret = Level::new(Palette::default(), 0, 0);
+    let mut has_grid = false;
+    // Patrols and grenades belong to enemies so they can only be given out once all sections have been read
+    let mut patrols = None;
+    let mut grenades = None;
+
+    for section in &sections {
+        match &*section.name {
+            "PALETTE" => {
+                let mats: Vec<String> = section.decode()?;
+                // Tiles refer to materials by a byte
+                if mats.len() > MAX_MATERIALS {
+                    return Err(section.error(format!("palette has {} materials but at most {} can be used", mats.len(), MAX_MATERIALS)));
+                }
+                ret.palette = Palette::new(mats.into_iter().map(sstr).collect());
+            }
+            "GRD" => {
+                ret.grid = section.decode()?;
+                check_grid(section, &ret.grid)?;
+                has_grid = true;
+            }
+            "START" => ret.start_point = Some(section.decode().map(|(x, y)| Point2::new(x, y))?),
+            "ENEMIES" => ret.enemies = section.decode()
+                .map(|enemies: Vec<Enemy>| enemies.into_iter().map(|mut e| {
+                    // Health isn't saved since it comes from the enemy's archetype
+                    e.pl.health = e.kind.health;
+                    e
+                }).collect())?,
+            "POINT GOAL" => ret.exit = Some(section.decode().map(|(x, y)| Point2::new(x, y))?),
+            "INTELS" => ret.intels = section.decode()
+                .map(|l: Vec<(f32, f32)>| l.into_iter().map(|(x, y)| Point2::new(x, y)).collect())?,
+            "DECS" => ret.decals = section.decode()?,
+            "PICKUPS" => ret.pickups = section.decode()
+                .map(|l: Vec<((f32, f32), u8)>| l.into_iter().map(|((x, y), i)| (Point2::new(x, y), i)).collect())?,
+            "WEPS" => {
+                let weps: Vec<((f32, f32), String)> = section.decode()?;
+                for ((x, y), id) in weps {
+                    match WEAPONS.get(&*id) {
+                        Some(wep) => ret.weapons.push(wep.make_drop(Point2::new(x, y))),
+                        None => ret.unknown_weapons.push((Point2::new(x, y), id)),
+                    }
+                }
+            }
+            "DOORS" => ret.doors = section.decode()?,
+            "ALARMS" => ret.alarms = section.decode()?,
+            "PATROLS" => patrols = Some((section, section.decode::<Vec<(u32, Vec<Waypoint>)>>()?)),
+            "GRENADES" => grenades = Some((section, section.decode::<Vec<(u32, u8)>>()?)),
+            name => warn!("Skipping unknown section `{}' at byte {}", name, section.offset),
+        }
+    }
+    if !has_grid {
+        return Err(LevelError::MissingSection{name: "GRD".to_owned(), offset: bytes.len()});
+    }
+    if let Some((section, patrols)) = patrols {
+        let enemies = ret.enemies.len();
+        for (i, route) in patrols {
+            let enemy = ret.enemies.get_mut(i as usize)
+                .ok_or_else(|| section.error(format!("patrol for enemy {} but there are only {} enemies", i, enemies)))?;
+            enemy.patrol = Patrol::new(route);
+        }
+    }
+    if let Some((section, grenades)) = grenades {
+        let enemies = ret.enemies.len();
+        for (i, n) in grenades {
+            let enemy = ret.enemies.get_mut(i as usize)
+                .ok_or_else(|| section.error(format!("grenades for enemy {} but there are only {} enemies", i, enemies)))?;
+            enemy.pl.wep.utilities.grenades = n;
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Makes sure the tiles of a grid fill out whole rows that fit in its dimensions
+fn check_grid(section: &Section, grid: &Grid) -> Result<(), LevelError> {
+    let width = grid.width as usize;
+    if width == 0 {
+        return Err(section.error("grid has a width of 0"));
+    }
+    if grid.mats.len() % width != 0 {
+        return Err(section.error(format!("{} tiles don't fill out rows of {}", grid.mats.len(), width)));
+    }
+    if grid.mats.len() / width > std::u16::MAX as usize {
+        return Err(section.error(format!("grid is {} tiles high", grid.mats.len() / width)));
+    }
+    Ok(())
+}
+
+/// Writes a level in the current version of the format
+pub fn write_level<W: Write>(level: &Level, mut file: W) -> Result<(), LevelError> {
+    let mut buf = Vec::with_capacity(MAGIC.len() + 2 + level.grid.mats.len() + 64);
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&LEVEL_VERSION.to_le_bytes());
+
+    let palette: Vec<&str> = level.palette.iter().collect();
+    write_section(&mut buf, "PALETTE", &palette)?;
+    write_section(&mut buf, "GRD", &level.grid)?;
+    if let Some(start) = level.start_point {
+        write_section(&mut buf, "START", &(start.x, start.y))?;
+    }
+    if !level.enemies.is_empty() {
+        write_section(&mut buf, "ENEMIES", &level.enemies)?;
+    }
+    let patrols: Vec<(u32, &[Waypoint])> = level.enemies.iter().enumerate()
+        .filter(|(_, e)| !e.patrol.route.is_empty())
+        .map(|(i, e)| (i as u32, &*e.patrol.route))
+        .collect();
+    if !patrols.is_empty() {
+        write_section(&mut buf, "PATROLS", &patrols)?;
+    }
+    let grenades: Vec<(u32, u8)> = level.enemies.iter().enumerate()
+        .filter(|(_, e)| e.pl.wep.utilities.grenades > 0)
+        .map(|(i, e)| (i as u32, e.pl.wep.utilities.grenades))
+        .collect();
+    if !grenades.is_empty() {
+        write_section(&mut buf, "GRENADES", &grenades)?;
+    }
+    if let Some(p) = level.exit {
+        write_section(&mut buf, "POINT GOAL", &(p.x, p.y))?;
+    }
+    if !level.intels.is_empty() {
+        let intels: Vec<_> = level.intels.iter().map(|p| (p.x, p.y)).collect();
+        write_section(&mut buf, "INTELS", &intels)?;
+    }
+    if !level.decals.is_empty() {
+        write_section(&mut buf, "DECS", &level.decals)?;
+    }
+    if !level.pickups.is_empty() {
+        let pickups: Vec<_> = level.pickups.iter().map(|&(p, i)| ((p.x, p.y), i)).collect();
+        write_section(&mut buf, "PICKUPS", &pickups)?;
+    }
+    if !level.weapons.is_empty() || !level.unknown_weapons.is_empty() {
+        let weapons: Vec<((f32, f32), &str)> = level.weapons.iter().map(|w| ((w.pos.x, w.pos.y), w.weapon.id))
+            .chain(level.unknown_weapons.iter().map(|(p, id)| ((p.x, p.y), &**id)))
+            .collect();
+        write_section(&mut buf, "WEPS", &weapons)?;
+    }
+    if !level.doors.is_empty() {
+        write_section(&mut buf, "DOORS", &level.doors)?;
+    }
+    if !level.alarms.is_empty() {
+        write_section(&mut buf, "ALARMS", &level.alarms)?;
+    }
+
+    // The checksum covers everything before the end section
+    let checksum = fnv1a(&buf);
+    write_section(&mut buf, "END", &checksum)?;
+
+    file.write_all(&buf)?;
+    Ok(())
+}
+
+/// Each section is its name and the length of its contents followed by the contents
+fn write_section<T: Serialize + ?Sized>(buf: &mut Vec<u8>, name: &str, val: &T) -> Result<(), LevelError> {
+    let section = Section::new(name, buf.len(), val)?;
+
+    buf.extend_from_slice(&(section.name.len() as u16).to_le_bytes());
+    buf.extend_from_slice(section.name.as_bytes());
+    buf.extend_from_slice(&(section.data.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&section.data);
+    Ok(())
+}
+
+/// Splits a file with the magic header into its sections
+fn read_sections(bytes: &[u8]) -> Result<(u16, Vec<Section>), LevelError> {
+    fn take<'a>(bytes: &'a [u8], offset: &mut usize, n: usize, start: usize) -> Result<&'a [u8], LevelError> {
+        let end = offset.checked_add(n).ok_or(LevelError::Truncated{offset: start})?;
+        let ret = bytes.get(*offset..end).ok_or(LevelError::Truncated{offset: start})?;
+        *offset = end;
+        Ok(ret)
+    }
+
+    let mut offset = MAGIC.len();
+    let mut version = [0; 2];
+    version.copy_from_slice(take(bytes, &mut offset, 2, 0)?);
+    let version = u16::from_le_bytes(version);
+
+    let mut sections = Vec::new();
+    loop {
+        let start = offset;
+        let mut name_len = [0; 2];
+        name_len.copy_from_slice(take(bytes, &mut offset, 2, start)?);
+        let name = take(bytes, &mut offset, u16::from_le_bytes(name_len) as usize, start)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        let mut len = [0; 8];
+        len.copy_from_slice(take(bytes, &mut offset, 8, start)?);
+        let data = take(bytes, &mut offset, u64::from_le_bytes(len) as usize, start)?.to_vec();
+
+        let section = Section {
+            name,
+            offset: start,
+            data,
+        };
+
+        if section.name == "END" {
+            let expected = fnv1a(&bytes[..start]);
+            let found = section.decode()?;
+            if expected != found {
+                break Err(LevelError::ChecksumMismatch{expected, found});
+            }
+            break Ok((version, sections));
+        }
+        sections.push(section);
+    }
+}
+
+/// Splits a file without the magic header into its sections
+///
+/// Sections in these files are a line with the name followed by the contents,
+/// so the type of every section has to be known to know where it ends.
+fn read_legacy_sections(bytes: &[u8]) -> Result<Vec<Section>, LevelError> {
+    let mut offset = 0;
+    let mut sections = Vec::new();
+
+    loop {
+        let start = offset;
+        let line_len = bytes[offset..].iter().position(|&b| b == b'\n').ok_or(LevelError::Truncated{offset: start})?;
+        let name = String::from_utf8_lossy(&bytes[offset..offset + line_len]).trim_end().to_owned();
+        offset += line_len + 1;
+
+        let len = match &*name {
+            "" => continue,
+            "END" => break Ok(sections),
+            "PALETTE" => measure::<Vec<String>>(&name, start, &bytes[offset..])?,
+            "GRD" => measure::<Grid>(&name, start, &bytes[offset..])?,
+            "GRID" => measure::<(usize, Vec<u16>)>(&name, start, &bytes[offset..])?,
+            "START" | "POINT GOAL" => measure::<(f32, f32)>(&name, start, &bytes[offset..])?,
+            "ENEMIES" => measure::<Vec<Object>>(&name, start, &bytes[offset..])?,
+            "INTELS" => measure::<Vec<(f32, f32)>>(&name, start, &bytes[offset..])?,
+            "DECORATIONS" => measure::<Vec<OldDecoration>>(&name, start, &bytes[offset..])?,
+            "DECS" => measure::<Vec<Decal>>(&name, start, &bytes[offset..])?,
+            "PICKUPS" => measure::<Vec<((f32, f32), u8)>>(&name, start, &bytes[offset..])?,
+            "WEPS" => measure::<Vec<((f32, f32), String)>>(&name, start, &bytes[offset..])?,
+            "WEAPONS" => measure::<Vec<((f32, f32), u8)>>(&name, start, &bytes[offset..])?,
+            "DOORS" => measure::<Vec<Door>>(&name, start, &bytes[offset..])?,
+            _ => break Err(LevelError::UnknownSection{name, offset: start}),
+        };
+
+        sections.push(Section {
+            name,
+            offset: start,
+            data: bytes[offset..offset + len].to_vec(),
+        });
+        offset += len;
+    }
+}
+
+/// How many bytes a value of type `T` takes up at the start of `bytes`
+fn measure<T: DeserializeOwned>(name: &str, offset: usize, mut bytes: &[u8]) -> Result<usize, LevelError> {
+    let len = bytes.len();
+    bincode::deserialize_from::<_, T>(&mut bytes).map_err(|e| LevelError::BadSection {
+        name: name.to_owned(),
+        offset,
+        error: e.to_string(),
+    })?;
+    Ok(len - bytes.len())
+}
+
+/// Replaces the legacy `GRID`, `WEAPONS` and `DECORATIONS` sections with their newer counterparts
+fn v0_to_v1(sections: &mut Vec<Section>) -> Result<(), LevelError> {
+    // Weapons in order of their old indices
+    const WEAPONS_OLD: [&str; 6] = [
+        "glock",
+        "five_seven",
+        "magnum",
+        "m4a1",
+        "ak47",
+        "arwp",
+    ];
+
+    for section in sections.iter_mut() {
+        *section = match &*section.name {
+            "GRID" => {
+                let (w, grid): (usize, Vec<u16>) = section.decode()?;
+                let grid = Grid {
+                    mats: grid.into_iter().map(|n| n as u8).collect(),
+                    width: w as u16,
+                    closed_doors: Default::default(),
+                };
+                Section::new("GRD", section.offset, &grid)?
+            }
+            "WEAPONS" => {
+                let weps: Vec<((f32, f32), u8)> = section.decode()?;
+                let weps = weps.into_iter()
+                    .map(|(p, i)| WEAPONS_OLD.get(i as usize)
+                        .map(|&id| (p, id))
+                        .ok_or_else(|| section.error(format!("no weapon with index {}", i))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Section::new("WEPS", section.offset, &weps)?
+            }
+            "DECORATIONS" => {
+                let decs: Vec<OldDecoration> = section.decode()?;
+                let decs: Vec<Decal> = decs.into_iter().map(OldDecoration::renew).collect();
+                Section::new("DECS", section.offset, &decs)?
+            }
+            _ => continue,
+        };
+    }
+    Ok(())
+}
+
+/// Gives every enemy the default archetype since enemies used to only be stored as their object
+fn v1_to_v2(sections: &mut Vec<Section>) -> Result<(), LevelError> {
+    for section in sections.iter_mut() {
+        if section.name == "ENEMIES" {
+            let objs: Vec<Object> = section.decode()?;
+            let enemies: Vec<Enemy> = objs.into_iter().map(|obj| Enemy::new(obj, Archetype::default_archetype())).collect();
+            *section = Section::new("ENEMIES", section.offset, &enemies)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn legacy_levels_migrate() {
+        let mut legacy = 0;
+        for entry in fs::read_dir("levels").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|e| e != "lvl").unwrap_or(true) {
+                continue;
+            }
+            let bytes = fs::read(&path).unwrap();
+            if format_version(&bytes) != 0 {
+                continue;
+            }
+            legacy += 1;
+
+            let mut sections = read_legacy_sections(&bytes).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            for migrate in &MIGRATIONS {
+                migrate(&mut sections).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            }
+            for section in &sections {
+                assert!(!["GRID", "WEAPONS", "DECORATIONS"].contains(&&*section.name),
+                    "{}: `{}' left over after migrating", path.display(), section.name);
+            }
+
+            let level = read_level(&bytes).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            let mut buf = Vec::new();
+            write_level(&level, &mut buf).unwrap();
+            assert_eq!(format_version(&buf), LEVEL_VERSION);
+            let reread = read_level(&buf).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(reread.enemies.len(), level.enemies.len(), "{}", path.display());
+            assert_eq!(reread.weapons.len(), level.weapons.len(), "{}", path.display());
+            assert_eq!(reread.decals.len(), level.decals.len(), "{}", path.display());
+        }
+        assert!(legacy > 0, "no legacy levels to migrate");
+    }
+
+    /// A file in the current format holding just the given sections
+    fn level_bytes(sections: &[(&str, &Grid)]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&LEVEL_VERSION.to_le_bytes());
+        for &(name, grid) in sections {
+            write_section(&mut bytes, name, grid).unwrap();
+        }
+        let checksum = fnv1a(&bytes);
+        write_section(&mut bytes, "END", &checksum).unwrap();
+        bytes
+    }
+
+    fn bad_grid(grid: Grid) {
+        match read_level(&level_bytes(&[("GRD", &grid)])) {
+            Err(LevelError::BadSection{name, offset, ..}) => {
+                assert_eq!(name, "GRD");
+                assert_eq!(offset, MAGIC.len() + 2);
+            }
+            r => panic!("expected a bad grid section, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn grid_without_width_is_rejected() {
+        bad_grid(Grid::new(0, 0));
+    }
+
+    #[test]
+    fn grid_with_partial_row_is_rejected() {
+        let mut grid = Grid::new(3, 2);
+        grid.mats.pop();
+        bad_grid(grid);
+    }
+
+    #[test]
+    fn level_without_grid_is_rejected() {
+        let bytes = level_bytes(&[]);
+        match read_level(&bytes) {
+            Err(LevelError::MissingSection{name, offset}) => {
+                assert_eq!(name, "GRD");
+                assert_eq!(offset, bytes.len());
+            }
+            r => panic!("expected a missing grid section, got {:?}", r.map(|_| ())),
+        }
+        assert!(read_level(&level_bytes(&[("GRD", &Grid::new(3, 2))])).is_ok());
+    }
+
+    #[test]
+    fn palette_too_big_for_tiles_is_rejected() {
+        let mats: Vec<String> = (0..=MAX_MATERIALS).map(|i| format!("mat{}", i)).collect();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&LEVEL_VERSION.to_le_bytes());
+        write_section(&mut bytes, "PALETTE", &mats).unwrap();
+        write_section(&mut bytes, "GRD", &Grid::new(1, 1)).unwrap();
+        let checksum = fnv1a(&bytes);
+        write_section(&mut bytes, "END", &checksum).unwrap();
+
+        match read_level(&bytes) {
+            Err(LevelError::BadSection{name, ..}) => assert_eq!(name, "PALETTE"),
+            r => panic!("expected a bad palette section, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn huge_section_is_truncated() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&LEVEL_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&3u16.to_le_bytes());
+        bytes.extend_from_slice(b"GRD");
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+
+        match read_level(&bytes) {
+            Err(LevelError::Truncated{offset}) => assert_eq!(offset, MAGIC.len() + 2),
+            r => panic!("expected a truncated section, got {:?}", r.map(|_| ())),
+        }
+    }
+}
@@ -38,9 +38,30 @@ fn ensure(mat: &str) {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize)]
 struct MaterialProperties {
     solid: bool,
+    /// Penetration power a bullet needs to go through a tile of a solid material
+    #[serde(default = "def_resistance")]
+    resistance: f32,
+    /// Chance of a bullet glancing off a solid material when hitting it at a shallow angle
+    #[serde(default)]
+    ricochet: f32,
+}
+
+impl Default for MaterialProperties {
+    fn default() -> Self {
+        MaterialProperties {
+            solid: false,
+            resistance: def_resistance(),
+            ricochet: 0.,
+        }
+    }
+}
+
+#[inline]
+const fn def_resistance() -> f32 {
+    std::f32::INFINITY
 }
 
 #[inline]
@@ -50,6 +71,20 @@ pub fn is_solid(mat: &str) -> bool {
     MATS.read().unwrap()[mat].props.solid
 }
 
+#[inline]
+pub fn resistance(mat: &str) -> f32 {
+    ensure(mat);
+
+    MATS.read().unwrap()[mat].props.resistance
+}
+
+#[inline]
+pub fn ricochet(mat: &str) -> f32 {
+    ensure(mat);
+
+    MATS.read().unwrap()[mat].props.ricochet
+}
+
 #[inline]
 pub fn get_img<'a>(ctx: &mut Context, assets: &'a Assets, mat: &str) -> Ref<'a, Image> {
     ensure(mat);
@@ -57,7 +92,7 @@ pub fn get_img<'a>(ctx: &mut Context, assets: &'a Assets, mat: &str) -> Ref<'a,
     assets.get_img(ctx, &MATS.read().unwrap()[mat].spr)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Palette {
     materials: Box<[&'static str]>,
 }
@@ -118,9 +153,22 @@ impl Palette {
         is_solid(self.materials[i as usize])
     }
     #[inline]
+    pub fn resistance(&self, i: u8) -> f32 {
+        resistance(self.materials[i as usize])
+    }
+    #[inline]
+    pub fn ricochet(&self, i: u8) -> f32 {
+        ricochet(self.materials[i as usize])
+    }
+    #[inline]
     pub fn get(&self, i: u8) -> Option<&str> {
         self.materials.get(i as usize).copied()
     }
+    /// The materials in the order of their indices
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.materials.iter().copied()
+    }
     #[inline]
     pub fn find(&self, mat: &str) -> Option<u8> {
         self.materials.iter().position(|s| &mat == s).map(|i| i as u8)
@@ -0,0 +1,67 @@
+use crate::util::Point2;
+use super::{Grid, Palette};
+
+/// How much of a sound's range is left after going through a wall
+const WALL_DAMPENING: f32 = 0.4;
+/// Distance between the points checked for walls along the way of a sound
+const STEP: f32 = 8.;
+
+/// Range of the sound of a grenade exploding
+pub const EXPLOSION_LOUDNESS: f32 = 1400.;
+/// Range of the sound of the player running
+pub const FOOTSTEP_LOUDNESS: f32 = 96.;
+/// Range of an enemy shouting to its allies about the player
+pub const SHOUT_LOUDNESS: f32 = 480.;
+
+/// A sound that enemies can hear
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    pub pos: Point2,
+    /// How far away the noise can be heard with no walls in the way
+    pub loudness: f32,
+}
+
+impl Noise {
+    #[inline]
+    pub fn new(pos: Point2, loudness: f32) -> Self {
+        Noise {
+            pos,
+            loudness,
+        }
+    }
+    /// How well the noise can be heard at `p`, walls in between dampening it
+    ///
+    /// Goes from 1 right at the noise to 0 where it can no longer be heard.
+    pub fn strength_at(&self, p: Point2, palette: &Palette, grid: &Grid) -> f32 {
+        let dist = (p - self.pos).norm();
+        if dist > self.loudness {
+            return 0.;
+        }
+        let walls = grid.walls_between(palette, self.pos, p);
+        let range = self.loudness * WALL_DAMPENING.powi(walls as i32);
+
+        (1. - dist / range).max(0.)
+    }
+}
+
+impl Grid {
+    /// Counts how many separate walls a straight line between two points goes through
+    pub fn walls_between(&self, pal: &Palette, from: Point2, to: Point2) -> u32 {
+        let dist = to - from;
+        let steps = (dist.norm() / STEP).ceil() as u32;
+
+        let mut walls = 0;
+        let mut in_wall = false;
+        for i in 0..=steps {
+            let p = from + dist * (i as f32 / steps.max(1) as f32);
+            let (x, y) = Grid::snap(p);
+            let solid = self.is_solid(pal, x, y);
+            if solid && !in_wall {
+                walls += 1;
+            }
+            in_wall = solid;
+        }
+
+        walls
+    }
+}
@@ -0,0 +1,273 @@
+use crate::util::{Point2, Vector2};
+use super::{Grid, Palette};
+
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+
+/// Cost of moving to an orthogonally adjacent tile
+const STRAIGHT: u32 = 10;
+/// Cost of moving to a diagonally adjacent tile (roughly `STRAIGHT * sqrt(2)`)
+const DIAGONAL: u32 = 14;
+/// How far from the centre line a smoothed path has to be clear of walls
+const CLEARANCE: f32 = 15.;
+
+const NEIGHBOURS: [(i32, i32, u32); 8] = [
+    (1, 0, STRAIGHT),
+    (-1, 0, STRAIGHT),
+    (0, 1, STRAIGHT),
+    (0, -1, STRAIGHT),
+    (1, 1, DIAGONAL),
+    (1, -1, DIAGONAL),
+    (-1, 1, DIAGONAL),
+    (-1, -1, DIAGONAL),
+];
+
+/// Centre of the tile at the given grid coordinates
+#[inline]
+pub fn tile_centre(x: u16, y: u16) -> Point2 {
+    Point2::new(f32::from(x) * 32. + 16., f32::from(y) * 32. + 16.)
+}
+
+#[inline]
+fn octile(a: (u16, u16), b: (u16, u16)) -> u32 {
+    let dx = (i32::from(a.0) - i32::from(b.0)).abs() as u32;
+    let dy = (i32::from(a.1) - i32::from(b.1)).abs() as u32;
+
+    STRAIGHT * dx.max(dy) + (DIAGONAL - STRAIGHT) * dx.min(dy)
+}
+
+impl Grid {
+    /// Finds a list of waypoints leading from `from` to `to` around solid tiles
+    ///
+    /// Doors are walked through as if they were open, the centre of any closed door on the way
+    /// is always one of the waypoints so that it can be opened before walking through it.
+    ///
+    /// The last waypoint is always `to` itself. Returns `None` if `to` cannot be reached
+    /// or if `from` is inside a wall.
+    pub fn find_path(&self, pal: &Palette, from: Point2, to: Point2) -> Option<Vec<Point2>> {
+        let start = Grid::snap(from);
+        let goal = Grid::snap(to);
+
+        if self.is_wall(pal, goal.0, goal.1) || self.is_wall(pal, start.0, start.1) {
+            return None;
+        }
+        if start == goal {
+            return Some(vec![to]);
+        }
+
+        let width = self.width() as usize;
+        let len = width * self.height() as usize;
+        let idx = |(x, y): (u16, u16)| x as usize + y as usize * width;
+
+        let mut costs = vec![std::u32::MAX; len];
+        let mut came_from = vec![std::usize::MAX; len];
+        let mut open = BinaryHeap::new();
+
+        costs[idx(start)] = 0;
+        open.push(Reverse((octile(start, goal), idx(start))));
+
+        while let Some(Reverse((_, i))) = open.pop() {
+            let cur = ((i % width) as u16, (i / width) as u16);
+            if cur == goal {
+                break;
+            }
+            let cost = costs[i];
+
+            for &(dx, dy, step) in &NEIGHBOURS {
+                let nx = i32::from(cur.0) + dx;
+                let ny = i32::from(cur.1) + dy;
+                if nx < 0 || ny < 0 {
+                    continue;
+                }
+                let next = (nx as u16, ny as u16);
+                if self.is_wall(pal, next.0, next.1) {
+                    continue;
+                }
+                // Don't cut corners when going diagonally
+                if dx != 0 && dy != 0 && (self.is_wall(pal, next.0, cur.1) || self.is_wall(pal, cur.0, next.1)) {
+                    continue;
+                }
+
+                let j = idx(next);
+                let new_cost = cost + step;
+                if new_cost < costs[j] {
+                    costs[j] = new_cost;
+                    came_from[j] = i;
+                    open.push(Reverse((new_cost + octile(next, goal), j)));
+                }
+            }
+        }
+
+        if came_from[idx(goal)] == std::usize::MAX {
+            return None;
+        }
+
+        let mut tiles = Vec::new();
+        let mut i = came_from[idx(goal)];
+        while i != idx(start) {
+            tiles.push(tile_centre((i % width) as u16, (i / width) as u16));
+            i = came_from[i];
+        }
+        tiles.reverse();
+        tiles.push(to);
+
+        Some(self.smooth_path(pal, from, tiles))
+    }
+    /// Removes waypoints that can be skipped by walking in a straight line
+    fn smooth_path(&self, pal: &Palette, from: Point2, tiles: Vec<Point2>) -> Vec<Point2> {
+        let mut path = Vec::with_capacity(tiles.len());
+        let mut cur = from;
+        let mut i = 0;
+
+        while i < tiles.len() {
+            let mut furthest = i;
+            for (j, &p) in tiles.iter().enumerate().skip(i + 1) {
+                if self.wide_clear(pal, cur, p) {
+                    furthest = j;
+                } else {
+                    break;
+                }
+            }
+            cur = tiles[furthest];
+            path.push(cur);
+            i = furthest + 1;
+        }
+
+        path
+    }
+    /// Checks whether something the size of a player can walk in a straight line between two points
+    fn wide_clear(&self, pal: &Palette, from: Point2, to: Point2) -> bool {
+        let dist = to - from;
+        let norm = dist.norm();
+        if norm == 0. {
+            return true;
+        }
+        let side = CLEARANCE / norm * Vector2::new(-dist.y, dist.x);
+
+        self.ray_cast(pal, from, dist, true).full()
+            && self.ray_cast(pal, from + side, dist, true).full()
+            && self.ray_cast(pal, from - side, dist, true).full()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALL: u8 = 1;
+    const FLOOR: u8 = 2;
+
+    /// Builds a grid from rows where `#` is a wall and anything else is floor
+    fn grid(rows: &[&str]) -> Grid {
+        let mut grid = Grid::new(rows[0].len() as u16, rows.len() as u16);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                grid.insert(x as u16, y as u16, if c == '#' { WALL } else { FLOOR });
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let pal = Palette::default();
+        let grid = grid(&[
+            "#######",
+            "#.....#",
+            "#####.#",
+            "#.....#",
+            "#######",
+        ]);
+        let from = tile_centre(1, 1);
+        let to = tile_centre(1, 3);
+
+        let path = grid.find_path(&pal, from, to).unwrap();
+
+        assert_eq!(path.last(), Some(&to));
+        let mut cur = from;
+        for &p in &path {
+            assert!(grid.wide_clear(&pal, cur, p), "{:?} to {:?} goes through a wall", cur, p);
+            cur = p;
+        }
+        assert!(path.iter().any(|p| Grid::snap(*p).0 == 5), "path doesn't use the gap: {:?}", path);
+    }
+
+    #[test]
+    fn path_does_not_cut_corners() {
+        let pal = Palette::default();
+        let grid = grid(&[
+            "####",
+            "#..#",
+            "#.##",
+            "####",
+        ]);
+        // (1, 2) to (2, 1) would be a diagonal step past the wall at (2, 2)
+        let from = tile_centre(1, 2);
+        let to = tile_centre(2, 1);
+
+        let path = grid.find_path(&pal, from, to).unwrap();
+
+        assert_eq!(path, vec![tile_centre(1, 1), to]);
+    }
+
+    #[test]
+    fn unreachable_target() {
+        let pal = Palette::default();
+        let grid = grid(&[
+            "#####",
+            "#.#.#",
+            "#####",
+        ]);
+
+        assert_eq!(grid.find_path(&pal, tile_centre(1, 1), tile_centre(3, 1)), None);
+        assert_eq!(grid.find_path(&pal, tile_centre(1, 1), tile_centre(2, 1)), None);
+    }
+
+    #[test]
+    fn no_path_out_of_a_wall() {
+        let pal = Palette::default();
+        let grid = grid(&[
+            "#####",
+            "#...#",
+            "#####",
+        ]);
+
+        assert_eq!(grid.find_path(&pal, tile_centre(0, 1), tile_centre(3, 1)), None);
+    }
+
+    #[test]
+    fn straight_path_is_smoothed() {
+        let pal = Palette::default();
+        let grid = grid(&[
+            "########",
+            "#......#",
+            "#......#",
+            "########",
+        ]);
+        let to = tile_centre(6, 2);
+
+        assert_eq!(grid.find_path(&pal, tile_centre(1, 1), to), Some(vec![to]));
+    }
+
+    #[test]
+    fn wide_clear_needs_room_on_both_sides() {
+        let pal = Palette::default();
+        let grid = grid(&[
+            "#######",
+            "#.....#",
+            "#.....#",
+            "#.....#",
+            "###.###",
+            "#.....#",
+            "#######",
+        ]);
+
+        // Straight through the middle of a one tile gap
+        assert!(grid.wide_clear(&pal, tile_centre(3, 2), tile_centre(3, 5)));
+        // Brushing past the edge of the gap
+        assert!(!grid.wide_clear(&pal, Point2::new(100., 80.), Point2::new(100., 176.)));
+        // Straight into a wall
+        assert!(!grid.wide_clear(&pal, tile_centre(1, 2), tile_centre(1, 5)));
+        assert!(grid.wide_clear(&pal, tile_centre(1, 1), tile_centre(1, 1)));
+    }
+}
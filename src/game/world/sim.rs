@@ -0,0 +1,826 @@
+use std::f32::consts::FRAC_1_SQRT_2 as COS_45_D;
+
+use crate::{
+    util::{Sstr, Vector2, Point2, angle_to_vec},
+    obj::{
+        Object,
+        bullet::{Bullet, Hit},
+        player::{Player, ActiveSlot},
+        enemy::{Enemy, Behaviour},
+        weapon::{Weapon, WeaponInstance, FireMode, WEAPONS},
+        grenade::{Grenade, GrenadeState, GrenadeUpdate, GrenadeDrop, RANGE as GRENADE_RANGE},
+        corpse::Corpse,
+    },
+    game::DELTA,
+};
+
+use super::{World, Grid, Palette, Noise, EXPLOSION_LOUDNESS, FOOTSTEP_LOUDNESS, SHOUT_LOUDNESS};
+
+/// Something that happened during a simulation step that the outside world might want to react to
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    /// A sound effect should be played
+    Sound(Sstr),
+    /// Someone was hit and should leave blood at the given object
+    Blood(Object),
+    /// A bullet left a hole in the wall at the given object
+    BulletHole(Object),
+    /// A gun was fired from the muzzle at the given object
+    Shot(Object, &'static Weapon),
+    /// The player died
+    Death,
+    /// All objectives have just been completed
+    Victory,
+    /// The level has been won and the victory celebration is over
+    Win,
+    /// Something made a noise enemies can hear
+    Noise(Noise),
+}
+
+/// A discrete action the player can take
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    Switch(ActiveSlot),
+    Drop,
+    Reload,
+    PickUp,
+    Shoot,
+    ThrowGrenade,
+    /// Opens or closes the nearest door
+    Interact,
+    /// Starts dragging the nearest body or lets go of the one being dragged
+    Drag,
+    /// Switches the active weapon to its next fire mode
+    SwitchFireMode,
+}
+
+/// The player's input for a single simulation step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    /// Direction the player wants to walk in
+    #[serde(serialize_with = "crate::io::save::vec_ser", deserialize_with = "crate::io::save::vec_des")]
+    pub movement: Vector2,
+    /// Angle the player is aiming at
+    pub aim: f32,
+    /// Whether the player is walking slowly
+    pub walking: bool,
+    /// Whether the fire button is held down
+    pub firing: bool,
+    /// Actions done since the last step
+    pub actions: Vec<Action>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            movement: Vector2::new(0., 0.),
+            aim: 0.,
+            walking: false,
+            firing: false,
+            actions: Vec::new(),
+        }
+    }
+}
+
+const VICTORY_CELEBRATION: f32 = 2.;
+/// How far to the side of the player enemies go around to when flanking
+const FLANK_DISTANCE: f32 = 256.;
+/// How many enemies have to be shooting at the player before one throws a grenade at them in the open
+const GRENADE_ENGAGING: usize = 3;
+/// Seconds between an enemy's grenades
+const GRENADE_COOLDOWN: f32 = 6.;
+/// Seconds until an enemy that couldn't find a good throw tries again
+const GRENADE_RETRY: f32 = 0.5;
+/// Factor on how fast enemies notice the player when they're running
+const RUNNING_CONSPICUITY: f32 = 1.6;
+/// Factor on how fast enemies notice the player when they've just shot
+const SHOOTING_CONSPICUITY: f32 = 3.;
+/// Seconds after a shot that the player still stands out from having shot
+const SHOT_CONSPICUITY_TIME: f32 = 0.5;
+/// Fraction of the player's speed left when dragging a body
+const DRAG_SPEED: f32 = 0.5;
+/// How far away enemies go to pick up weapons, ammo and health they can see
+const SCAVENGE_RADIUS: f32 = 320.;
+/// How close an enemy has to be to something to pick it up
+const ENEMY_PICKUP_REACH: f32 = 16.;
+/// Fraction of its starting hp under which an enemy goes for health pickups
+const SCAVENGE_HEALTH: f32 = 0.5;
+/// Fraction of the inaccuracy from running that's left when walking
+const WALKING_INACCURACY: f32 = 0.25;
+
+impl World {
+    /// Advances the world by one tick given the player's input
+    pub fn step(&mut self, input: &Input) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+
+        self.since_shot += DELTA;
+        self.player.obj.rot = input.aim;
+        for &action in &input.actions {
+            self.act(action, input, &mut events);
+        }
+
+        self.update_grenades(&mut events);
+        self.update_bullets(&mut events);
+        self.update_collectables(&mut events);
+        self.update_player(input, &mut events);
+        self.update_enemies(input, &mut events);
+        self.update_victory(&mut events);
+
+        events
+    }
+    /// The index of the weapon drop the player can currently pick up
+    pub fn weapon_in_reach(&self) -> Option<usize> {
+        self.weapons.iter().rposition(|weapon| (weapon.pos-self.player.obj.pos).norm() <= 29.)
+    }
+    /// The index of the body the player can currently drag
+    pub fn corpse_in_reach(&self) -> Option<usize> {
+        self.corpses.iter().rposition(|corpse| corpse.in_reach(self.player.obj.pos))
+    }
+    /// The index of the closest door that can be opened or closed from `p`
+    pub fn door_in_reach(&self, p: Point2) -> Option<usize> {
+        self.doors.iter()
+            .enumerate()
+            .filter(|(_, door)| door.in_reach(p))
+            .min_by(|(_, a), (_, b)| (a.obj.pos-p).norm().partial_cmp(&(b.obj.pos-p).norm()).unwrap())
+            .map(|(i, _)| i)
+    }
+    /// Opens or closes a door unless it is being closed on someone standing in it
+    fn toggle_door(&mut self, i: usize, events: &mut Vec<GameEvent>) {
+        let tile = self.doors[i].tile();
+        if !self.doors[i].open {
+            self.doors[i].open = true;
+        } else if Grid::snap(self.player.obj.pos) != tile && self.enemies.iter().all(|e| Grid::snap(e.pl.obj.pos) != tile) {
+            self.doors[i].open = false;
+        } else {
+            return;
+        }
+        self.grid.set_door(tile, !self.doors[i].open);
+        events.push(GameEvent::Sound("cock2"));
+    }
+    fn act(&mut self, action: Action, input: &Input, events: &mut Vec<GameEvent>) {
+        match action {
+            Action::Switch(slot) => self.player.wep.switch(slot),
+            Action::Drop => {
+                if let Some(wep) = self.player.wep.take_active() {
+                    self.weapons.push(wep.into_drop(self.player.obj.pos));
+                } else {
+                    warn!("Dropped nothing");
+                }
+            }
+            Action::Reload => {
+                if let Some(wep) = self.player.wep.get_active_mut() {
+                    wep.reload(events)
+                } else {
+                    let weapon = &WEAPONS["glock"];
+                    self.bullets.push(Bullet{obj: self.player.obj.clone(), vel: Vector2::new(weapon.bullet_speed, 0.), damage: weapon.damage, origin: self.player.obj.pos, weapon});
+                }
+            }
+            Action::PickUp => {
+                if let Some(i) = self.weapon_in_reach() {
+                    if let Some(new_drop) = self.player.wep.add_weapon(WeaponInstance::from_drop(self.weapons.remove(i))) {
+                        self.weapons.push(new_drop.into_drop(self.player.obj.pos));
+                    }
+                }
+            }
+            Action::Shoot => {
+                if let Some(wep) = self.player.wep.get_active_mut() {
+                    if let Some(bm) = wep.shoot(events) {
+                        self.since_shot = 0.;
+                        events.push(GameEvent::Noise(Noise::new(self.player.obj.pos, wep.weapon.loudness)));
+                        let pos = self.player.obj.pos + 20. * angle_to_vec(self.player.obj.rot);
+                        let mut bul = Object::new(pos);
+                        bul.rot = self.player.obj.rot;
+
+                        events.push(GameEvent::Shot(bul.clone(), wep.weapon));
+                        self.bullets.extend(bm.make(bul, &mut self.rng, move_inaccuracy(input, wep.weapon)));
+                    }
+                } else {
+                    self.stab(events);
+                }
+            }
+            Action::ThrowGrenade => {
+                if let Some(gm) = self.player.wep.utilities.throw_grenade(events) {
+                    let pos = Grenade::throw_point(self.player.obj.pos, self.player.obj.rot);
+                    let mut gren = Object::new(pos);
+                    gren.rot = self.player.obj.rot;
+
+                    self.grenades.push(gm.make(gren));
+                }
+            }
+            Action::Interact => {
+                if let Some(i) = self.door_in_reach(self.player.obj.pos) {
+                    self.toggle_door(i, events);
+                }
+            }
+            Action::Drag => {
+                self.dragging = if self.dragging.is_some() {
+                    None
+                } else {
+                    self.corpse_in_reach()
+                };
+            }
+            Action::SwitchFireMode => {
+                if let Some(wep) = self.player.wep.get_active_mut() {
+                    wep.switch_fire_mode(events);
+                }
+            }
+        }
+    }
+    fn stab(&mut self, events: &mut Vec<GameEvent>) {
+        if !self.player.wep.melee.swing() {
+            return;
+        }
+        let player = &self.player;
+        let weapon = player.wep.melee.weapon;
+        let mut backstab = false;
+        let mut hit = false;
+        let mut takedown = false;
+        let mut dead = None;
+
+        for (i, enemy) in self.enemies.iter_mut().enumerate() {
+            if weapon.in_melee_reach(&player.obj, enemy.pl.obj.pos) {
+                backstab = is_backstab(&enemy.pl.obj, player.obj.pos);
+                hit = true;
+
+                events.push(GameEvent::Blood(enemy.pl.obj.clone()));
+                enemy.pl.weapon_damage(weapon.melee_damage(backstab), weapon.penetration);
+                if enemy.pl.health.is_dead() {
+                    // Taking down someone from behind who doesn't know about the player is silent
+                    takedown = backstab && !enemy.behaviour.is_alert();
+                    dead = Some(i);
+                    break;
+                }
+                enemy.hurt(player.obj.pos);
+                self.detected = true;
+            }
+        }
+        if hit && !takedown {
+            events.push(GameEvent::Noise(Noise::new(player.obj.pos, weapon.loudness)));
+        }
+        if let Some(i) = dead {
+            if !takedown {
+                events.push(GameEvent::Sound("death"));
+            }
+            self.kill_enemy(i);
+        }
+
+        events.push(GameEvent::Sound(weapon.melee_snd(backstab)));
+    }
+    fn kill_enemy(&mut self, i: usize) {
+        let Enemy{pl: Player{wep, obj, ..}, kind, ..}
+            = self.enemies.remove(i);
+        let pos = obj.pos;
+        self.corpses.push(Corpse::new(obj, kind.sprite));
+        if wep.utilities.grenades > 0 {
+            self.grenade_drops.push(GrenadeDrop{pos, grenades: wep.utilities.grenades});
+        }
+        for wep in wep {
+            self.weapons.push(wep.into_drop(pos));
+        }
+    }
+    fn hit_player(&self, blood: Object, events: &mut Vec<GameEvent>) {
+        events.push(GameEvent::Blood(blood));
+        events.push(GameEvent::Sound("hit"));
+
+        if self.player.health.is_dead() {
+            events.push(GameEvent::Death);
+            events.push(GameEvent::Sound("death"));
+        } else {
+            events.push(GameEvent::Sound("hurt"));
+        }
+    }
+    /// Handles an enemy having been hit by something coming from `source`
+    fn hit_enemy(&mut self, i: usize, blood: Object, source: Point2, events: &mut Vec<GameEvent>) {
+        events.push(GameEvent::Sound("hit"));
+        events.push(GameEvent::Blood(blood));
+
+        let enemy = &mut self.enemies[i];
+        if enemy.pl.health.is_dead() {
+            events.push(GameEvent::Sound("death"));
+            self.kill_enemy(i);
+        } else {
+            enemy.hurt(source);
+            self.detected = true;
+            events.push(GameEvent::Sound("hurt"));
+        }
+    }
+    fn update_grenades(&mut self, events: &mut Vec<GameEvent>) {
+        for i in (0..self.grenades.len()).rev() {
+            let g_update = self.grenades[i].update(&self.palette, &self.grid, &mut self.player, &mut self.enemies);
+
+            match g_update {
+                GrenadeUpdate::Explosion{player_hit, mut enemy_hits} => {
+                    events.push(GameEvent::Sound("boom"));
+                    let pos = self.grenades[i].obj.pos;
+                    events.push(GameEvent::Noise(Noise::new(pos, EXPLOSION_LOUDNESS)));
+
+                    if player_hit {
+                        self.hit_player(self.player.obj.clone(), events);
+                    }
+                    // Go from the back so that killing an enemy doesn't shift the ones left to hit
+                    enemy_hits.sort_unstable_by(|a, b| b.cmp(a));
+                    for e in enemy_hits {
+                        let blood = self.enemies[e].pl.obj.clone();
+                        self.hit_enemy(e, blood, pos, events);
+                    }
+                }
+                GrenadeUpdate::Dead => {
+                    self.grenades.remove(i);
+                }
+                GrenadeUpdate::None => (),
+            }
+        }
+    }
+    fn update_bullets(&mut self, events: &mut Vec<GameEvent>) {
+        for i in (0..self.bullets.len()).rev() {
+            let hit = self.bullets[i].update(&self.palette, &self.grid, &mut self.player, &mut self.enemies, &mut self.rng);
+
+            match hit {
+                Hit::None => (),
+                Hit::Through(hole) | Hit::Ricochet(hole) => {
+                    events.push(GameEvent::Sound(self.bullets[i].weapon.impact_snd));
+                    events.push(GameEvent::BulletHole(hole));
+                }
+                Hit::Wall => {
+                    let mut bullet = self.bullets.remove(i);
+                    events.push(GameEvent::Sound(bullet.weapon.impact_snd));
+                    let dir = angle_to_vec(bullet.obj.rot);
+                    bullet.obj.pos += Vector2::new(5.*dir.x.signum(), 5.*dir.y.signum());
+                    events.push(GameEvent::BulletHole(bullet.obj));
+                }
+                Hit::Player => {
+                    let bullet = self.bullets.remove(i);
+                    self.hit_player(bullet.obj, events);
+                }
+                Hit::Enemy(e) => {
+                    let bullet = self.bullets.remove(i);
+                    let pos = bullet.obj.pos;
+                    self.hit_enemy(e, bullet.obj, pos, events);
+                }
+            }
+        }
+    }
+    fn update_collectables(&mut self, events: &mut Vec<GameEvent>) {
+        let player_pos = self.player.obj.pos;
+        let intels_before = self.intels.len();
+        self.intels.retain(|&intel| (intel-player_pos).norm() > 15.);
+        for _ in self.intels.len()..intels_before {
+            events.push(GameEvent::Sound("hit"));
+        }
+
+        for i in (0..self.pickups.len()).rev() {
+            if (self.pickups[i].pos-player_pos).norm() <= 15. && self.pickups[i].apply(&mut self.player.health) {
+                self.pickups.remove(i);
+                events.push(GameEvent::Sound("hit"));
+            }
+        }
+
+        let utilities = &mut self.player.wep.utilities;
+        let drops_before = self.grenade_drops.len();
+        self.grenade_drops.retain(|drop| if (drop.pos-player_pos).norm() <= 15. {
+            utilities.grenades = utilities.grenades.saturating_add(drop.grenades);
+            false
+        } else {
+            true
+        });
+        if self.grenade_drops.len() < drops_before {
+            events.push(GameEvent::Sound("cock"));
+        }
+    }
+    fn update_enemies(&mut self, input: &Input, events: &mut Vec<GameEvent>) {
+        let player_pos = self.player.obj.pos;
+        let player_vel = input.movement;
+        let mut conspicuity = 1.;
+        if !input.walking && input.movement.norm_squared() != 0. {
+            conspicuity *= RUNNING_CONSPICUITY;
+        }
+        if self.since_shot < SHOT_CONSPICUITY_TIME {
+            conspicuity *= SHOOTING_CONSPICUITY;
+        }
+
+        let noises: Vec<Noise> = events.iter()
+            .filter_map(|e| if let GameEvent::Noise(noise) = *e { Some(noise) } else { None })
+            .collect();
+
+        let mut spotters = Vec::new();
+        let mut alerted = Vec::new();
+        let mut found_bodies = Vec::new();
+        for (i, enemy) in self.enemies.iter_mut().enumerate() {
+            enemy.grenade_cooldown = (enemy.grenade_cooldown - DELTA).max(0.);
+            for noise in &noises {
+                let strength = noise.strength_at(enemy.pl.obj.pos, &self.palette, &self.grid);
+                if strength > 0. {
+                    enemy.hear(noise.pos, strength);
+                }
+            }
+            let noticed = match enemy.sight(player_pos, &self.palette, &self.grid) {
+                Some(visibility) => enemy.detect(player_pos, visibility, conspicuity),
+                None => {
+                    enemy.lose_sight();
+                    false
+                }
+            };
+            if noticed {
+                if !enemy.behaviour.is_alert() {
+                    alerted.push(i);
+                }
+                spotters.push(i);
+                enemy.see(player_pos, player_vel);
+                enemy.spotted += DELTA;
+
+                // Someone only seen out of the corner of the eye has to be turned towards before shooting at them
+                let reacted = enemy.spotted >= enemy.kind.reaction_time + self.difficulty.reaction_time;
+                if reacted && enemy.behaviour.is_fighting() && enemy.faces(player_pos) {
+                    if let Some(wep) = enemy.pl.wep.get_active_mut() {
+                        wep.hold_trigger(true);
+                        if let Some(bm) = wep.shoot(events) {
+                            let pos = enemy.pl.obj.pos + 20. * angle_to_vec(enemy.pl.obj.rot);
+                            let mut bul = Object::new(pos);
+                            bul.rot = enemy.pl.obj.rot;
+
+                            events.push(GameEvent::Shot(bul.clone(), wep.weapon));
+                            self.bullets.extend(bm.make(bul, &mut self.rng, self.difficulty.aim_error));
+                        }
+                    }
+                }
+            }
+            if !enemy.behaviour.is_alert() {
+                let palette = &self.palette;
+                let grid = &self.grid;
+                // Bodies are only found by looking straight at them
+                let corpse = self.corpses.iter_mut()
+                    .find(|corpse| !corpse.discovered && enemy.sight(corpse.obj.pos, palette, grid).map(|v| v >= 1.).unwrap_or(false));
+                if let Some(corpse) = corpse {
+                    corpse.discovered = true;
+                    enemy.discover_body(corpse.obj.pos, palette, grid);
+                    found_bodies.push((i, corpse.obj.pos));
+                }
+            }
+            enemy.update(events, &self.palette, &self.grid, self.difficulty);
+
+            // Open any closed door between the enemy and its next waypoint,
+            // including ones that were closed after the path was planned
+            if let Some(&waypoint) = enemy.path.last() {
+                let pos = enemy.pl.obj.pos;
+                let cast = self.grid.ray_cast(&self.palette, pos, waypoint - pos, true);
+                let tile = cast.half_vec().map(|to_wall| Grid::snap(cast.into_point() + 0.5 * to_wall));
+                let grid = &self.grid;
+                if let Some(tile) = tile.filter(|&(x, y)| grid.is_closed_door(x, y)) {
+                    if let Some(door) = self.doors.iter_mut().find(|door| door.tile() == tile && door.in_reach(pos)) {
+                        door.open = true;
+                        self.grid.set_door(tile, false);
+                        events.push(GameEvent::Sound("cock2"));
+                    }
+                }
+            }
+        }
+
+        if !spotters.is_empty() {
+            self.detected = true;
+        }
+        self.update_melee(events);
+        self.update_enemy_pickups(events);
+        self.scavenge();
+        self.report_bodies(&found_bodies);
+        self.throw_enemy_grenades(&spotters, events);
+        self.alert_allies(&spotters, alerted);
+        self.update_alarms(events);
+    }
+    /// Lets fighting enemies throw grenades where the player is hiding,
+    /// or at the player when enough of them are already shooting
+    fn throw_enemy_grenades(&mut self, spotters: &[usize], events: &mut Vec<GameEvent>) {
+        // Wait for any grenade that's been thrown to go off first
+        if self.grenades.iter().any(|g| if let GrenadeState::Fused{..} = g.state { true } else { false }) {
+            return;
+        }
+        let engaging = spotters.iter().filter(|&&i| self.enemies[i].behaviour.is_fighting()).count();
+
+        for i in 0..self.enemies.len() {
+            let enemy = &self.enemies[i];
+            let target = match enemy.behaviour {
+                Behaviour::Combat{pos, ..} => pos,
+                _ => continue,
+            };
+            if enemy.grenade_cooldown > 0. || enemy.pl.wep.utilities.grenades == 0 {
+                continue;
+            }
+            if spotters.contains(&i) && engaging < GRENADE_ENGAGING {
+                continue;
+            }
+            let from = enemy.pl.obj.pos;
+            // Don't throw where it would hurt anyone on the same side, the thrower included
+            let allies_in_range = self.enemies.iter().any(|e| (e.pl.obj.pos - target).norm() <= GRENADE_RANGE);
+            if allies_in_range {
+                continue;
+            }
+
+            let enemy = &mut self.enemies[i];
+            let (rot, speed) = match Grenade::aim(from, target, &self.palette, &self.grid) {
+                Some(throw) => throw,
+                None => {
+                    enemy.grenade_cooldown = GRENADE_RETRY;
+                    continue;
+                }
+            };
+            if let Some(gm) = enemy.pl.wep.utilities.throw_grenade(events) {
+                enemy.pl.obj.rot = rot;
+                enemy.grenade_cooldown = GRENADE_COOLDOWN;
+
+                let mut gren = Object::new(Grenade::throw_point(from, rot));
+                gren.rot = rot;
+                self.grenades.push(gm.with_speed(speed).make(gren));
+                return;
+            }
+        }
+    }
+    /// Lets enemies that see the player tell allies that can hear or see them,
+    /// newly alerted allies taking turns to converge or flank from either side
+    fn alert_allies(&mut self, spotters: &[usize], mut alerted: Vec<usize>) {
+        let player = self.player.obj.pos;
+        let mut approach = 0;
+
+        for &s in spotters {
+            let spotter = self.enemies[s].pl.obj.pos;
+            let shout = Noise::new(spotter, SHOUT_LOUDNESS);
+
+            for (i, ally) in self.enemies.iter_mut().enumerate() {
+                if i == s {
+                    continue;
+                }
+                let pos = ally.pl.obj.pos;
+                let heard = shout.strength_at(pos, &self.palette, &self.grid) > 0.;
+                let seen = (pos - spotter).norm() <= ally.kind.view_distance
+                    && self.grid.ray_cast(&self.palette, pos, spotter - pos, true).full();
+                if !heard && !seen {
+                    continue;
+                }
+
+                if ally.behaviour.is_alert() {
+                    ally.inform(player, None);
+                    continue;
+                }
+                let via = match approach % 3 {
+                    0 => None,
+                    n => flank_point(&self.grid, &self.palette, spotter, player, if n == 1 { 1. } else { -1. }),
+                };
+                approach += 1;
+                ally.inform(player, via);
+                alerted.insert(0, i);
+            }
+        }
+
+        // Send someone who just found out about the player to the nearest alarm if no one is on it already,
+        // preferably an ally so the ones who can see the player keep fighting
+        if self.enemies.iter().any(|e| if let Behaviour::RaiseAlarm{..} = e.behaviour { true } else { false }) {
+            return;
+        }
+        if let Some(&i) = alerted.first() {
+            let pos = self.enemies[i].pl.obj.pos;
+            let alarm = self.alarms.iter()
+                .filter(|alarm| !alarm.raised)
+                .map(|alarm| alarm.obj.pos)
+                .min_by(|a, b| (a - pos).norm().partial_cmp(&(b - pos).norm()).unwrap());
+            if let Some(alarm) = alarm {
+                self.enemies[i].raise_alarm(alarm, player);
+            }
+        }
+    }
+    /// Winds up the melee strikes of enemies fighting up close and lands them if the player hasn't dodged
+    fn update_melee(&mut self, events: &mut Vec<GameEvent>) {
+        let player_pos = self.player.obj.pos;
+        let mut hits = 0;
+
+        for enemy in &mut self.enemies {
+            let melee = if let Behaviour::Melee{..} = enemy.behaviour { true } else { false };
+            if !melee {
+                enemy.windup = None;
+            }
+            let weapon = enemy.pl.wep.melee.weapon;
+
+            match enemy.windup {
+                Some(time) if time > DELTA => enemy.windup = Some(time - DELTA),
+                Some(_) => {
+                    enemy.windup = None;
+                    enemy.pl.wep.melee.swing();
+
+                    if weapon.in_melee_reach(&enemy.pl.obj, player_pos) {
+                        let backstab = is_backstab(&self.player.obj, enemy.pl.obj.pos);
+                        self.player.weapon_damage(weapon.melee_damage(backstab), weapon.penetration);
+                        events.push(GameEvent::Sound(weapon.melee_snd(backstab)));
+                        hits += 1;
+                    }
+                }
+                None => if let FireMode::Melee{range, windup, ..} = weapon.fire_mode {
+                    let ready = enemy.pl.wep.melee.loading_time == 0.;
+                    if melee && ready && enemy.spotted > 0. && (player_pos - enemy.pl.obj.pos).norm() < range {
+                        enemy.windup = Some(windup);
+                    }
+                },
+            }
+        }
+        for _ in 0..hits {
+            self.hit_player(self.player.obj.clone(), events);
+        }
+    }
+    /// Sends fighting enemies that are short on weapons, ammo or health to something nearby to pick up
+    fn scavenge(&mut self) {
+        let palette = &self.palette;
+        let grid = &self.grid;
+        let weapons = &self.weapons;
+        let pickups = &self.pickups;
+
+        for enemy in &mut self.enemies {
+            let pos = enemy.pl.obj.pos;
+            let from = match enemy.behaviour {
+                // Stick to stabbing if the player's right there
+                Behaviour::Melee{pos: p} if (p - pos).norm() <= 2. * enemy.pl.wep.melee.weapon.melee_range() => continue,
+                Behaviour::Combat{pos, ..} | Behaviour::Melee{pos} => pos,
+                Behaviour::TakeCover{from, ..} | Behaviour::Peek{from, ..} => from,
+                Behaviour::Scavenge{pos: target, from} => {
+                    // Give up if someone else got to it first
+                    let gone = !weapons.iter().any(|w| w.pos == target) && !pickups.iter().any(|p| p.pos == target);
+                    if gone {
+                        enemy.behaviour = Behaviour::Combat{pos: from, vel: Vector2::new(0., 0.)};
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let in_sight = |p: Point2| (p - pos).norm() <= SCAVENGE_RADIUS
+                && grid.ray_cast(palette, pos, p - pos, true).full();
+
+            let target = weapons.iter()
+                .filter(|w| (w.cur_clip > 0 || w.ammo > 0) && enemy.wants(w.weapon) && in_sight(w.pos))
+                .max_by(|a, b| a.weapon.power().partial_cmp(&b.weapon.power()).unwrap()
+                    .then((b.pos - pos).norm().partial_cmp(&(a.pos - pos).norm()).unwrap()))
+                .map(|w| w.pos)
+                .or_else(|| if enemy.pl.health.hp < SCAVENGE_HEALTH * enemy.kind.health.hp {
+                    pickups.iter()
+                        .map(|p| p.pos)
+                        .filter(|&p| in_sight(p))
+                        .min_by(|a, b| (a - pos).norm().partial_cmp(&(b - pos).norm()).unwrap())
+                } else {
+                    None
+                });
+
+            if let Some(target) = target {
+                enemy.behaviour = Behaviour::Scavenge{pos: target, from};
+            }
+        }
+    }
+    /// Lets enemies pick up weapons and ammo they want and health they need from where they're standing
+    fn update_enemy_pickups(&mut self, events: &mut Vec<GameEvent>) {
+        for enemy in &mut self.enemies {
+            let pos = enemy.pl.obj.pos;
+
+            let wanted = self.weapons.iter()
+                .position(|w| (w.pos - pos).norm() <= ENEMY_PICKUP_REACH && (w.cur_clip > 0 || w.ammo > 0) && enemy.wants(w.weapon));
+            if let Some(i) = wanted {
+                let drop = self.weapons.remove(i);
+                if let Some(wep) = enemy.pl.wep.find_mut(drop.weapon) {
+                    // Top up the one already carried
+                    wep.ammo = wep.ammo.saturating_add(drop.cur_clip).saturating_add(drop.ammo);
+                } else if let Some(old) = enemy.pl.wep.add_weapon(WeaponInstance::from_drop(drop)) {
+                    self.weapons.push(old.into_drop(pos));
+                }
+                enemy.pl.wep.active = enemy.pl.wep.best_slot();
+                events.push(GameEvent::Sound("cock"));
+            }
+
+            if enemy.pl.health.hp < enemy.kind.health.hp {
+                let health = &mut enemy.pl.health;
+                let pickups_before = self.pickups.len();
+                self.pickups.retain(|p| (p.pos - pos).norm() > ENEMY_PICKUP_REACH || !p.apply(health));
+                if self.pickups.len() < pickups_before {
+                    events.push(GameEvent::Sound("hit"));
+                }
+            }
+        }
+    }
+    /// Lets enemies who've found a body shout for the others who can hear them to come search too
+    fn report_bodies(&mut self, found: &[(usize, Point2)]) {
+        for &(f, body) in found {
+            let shout = Noise::new(self.enemies[f].pl.obj.pos, SHOUT_LOUDNESS);
+
+            for (i, ally) in self.enemies.iter_mut().enumerate() {
+                if i != f && shout.strength_at(ally.pl.obj.pos, &self.palette, &self.grid) > 0. {
+                    ally.discover_body(body, &self.palette, &self.grid);
+                }
+            }
+        }
+    }
+    /// Raises any alarm an enemy has run to, alerting every enemy in the level
+    fn update_alarms(&mut self, events: &mut Vec<GameEvent>) {
+        let player = self.player.obj.pos;
+        let mut raised = false;
+
+        for enemy in &self.enemies {
+            if let Behaviour::RaiseAlarm{..} = enemy.behaviour {
+                let pos = enemy.pl.obj.pos;
+                if let Some(alarm) = self.alarms.iter_mut().find(|alarm| !alarm.raised && alarm.in_reach(pos)) {
+                    alarm.raised = true;
+                    raised = true;
+                }
+            }
+        }
+        if raised {
+            events.push(GameEvent::Sound("ding"));
+            for enemy in &mut self.enemies {
+                enemy.hear_alarm(player);
+            }
+        }
+    }
+    fn update_player(&mut self, input: &Input, events: &mut Vec<GameEvent>) {
+        let mut speed = if input.walking {
+            100.
+        } else {
+            200.
+        };
+        if self.dragging.is_some() {
+            speed *= DRAG_SPEED;
+        }
+        self.player.wep.melee.update(events);
+        if let Some(wep) = self.player.wep.get_active_mut() {
+            wep.update(events);
+            wep.hold_trigger(input.firing);
+            // Bursts keep going after the trigger's let go
+            if wep.cur_clip > 0 && (input.firing && wep.fire_mode().is_auto() || wep.burst > 0) {
+                if let Some(bm) = wep.shoot(events) {
+                    self.since_shot = 0.;
+                    events.push(GameEvent::Noise(Noise::new(self.player.obj.pos, wep.weapon.loudness)));
+                    let pos = self.player.obj.pos + 20. * angle_to_vec(self.player.obj.rot);
+                    let mut bul = Object::new(pos);
+                    bul.rot = self.player.obj.rot;
+
+                    events.push(GameEvent::Shot(bul.clone(), wep.weapon));
+                    self.bullets.extend(bm.make(bul, &mut self.rng, move_inaccuracy(input, wep.weapon)));
+                }
+            }
+        }
+        if !input.walking && input.movement.norm_squared() != 0. {
+            events.push(GameEvent::Noise(Noise::new(self.player.obj.pos, FOOTSTEP_LOUDNESS)));
+        }
+        self.player.obj.move_on_grid(input.movement, speed, &self.palette, &self.grid);
+        if let Some(i) = self.dragging {
+            if !self.corpses[i].drag(self.player.obj.pos, &self.palette, &self.grid) {
+                self.dragging = None;
+            }
+        }
+    }
+    fn update_victory(&mut self, events: &mut Vec<GameEvent>) {
+        let game_won = match self.exit {
+            Some(p) => self.intels.is_empty() && (p - self.player.obj.pos).norm() < 32.,
+            None => self.enemies.is_empty(),
+        };
+
+        if game_won && self.victory_time <= 0. {
+            events.push(GameEvent::Victory);
+            events.push(GameEvent::Sound("victory"));
+            self.victory_time += DELTA;
+        } else if self.victory_time > 0. {
+            self.victory_time += DELTA;
+        } else {
+            self.time += 1;
+        }
+        if self.victory_time >= VICTORY_CELEBRATION {
+            events.push(GameEvent::Win);
+        }
+    }
+}
+
+/// How far off the player's shots with `weapon` go from moving while firing
+fn move_inaccuracy(input: &Input, weapon: &Weapon) -> f32 {
+    if input.movement.norm_squared() == 0. {
+        0.
+    } else if input.walking {
+        WALKING_INACCURACY * weapon.move_inaccuracy
+    } else {
+        weapon.move_inaccuracy
+    }
+}
+
+/// Whether a stab from `from` at someone standing as `target` comes from behind them
+fn is_backstab(target: &Object, from: Point2) -> bool {
+    let dist = from - target.pos;
+    angle_to_vec(target.rot).dot(&dist) / dist.norm() < COS_45_D
+}
+
+/// A point to the side of the player as seen from `from` to go around to,
+/// `side` being 1 for the left and -1 for the right
+fn flank_point(grid: &Grid, palette: &Palette, from: Point2, player: Point2, side: f32) -> Option<Point2> {
+    let dir = player - from;
+    if dir.norm_squared() == 0. {
+        return None;
+    }
+    let dir = dir.normalize();
+    let side = side * Vector2::new(dir.y, -dir.x);
+
+    let hit = grid.ray_cast(palette, player, FLANK_DISTANCE * side, true).into_point();
+    // Stay a bit away from walls
+    let dist = (hit - player).norm() - 16.;
+    if dist >= 48. {
+        Some(player + dist * side)
+    } else {
+        None
+    }
+}
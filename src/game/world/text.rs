@@ -0,0 +1,346 @@
+use crate::{
+    util::{Point2, sstr},
+    obj::{
+        Object,
+        enemy::{Enemy, Patrol, Waypoint, ARCHETYPES, DEFAULT_ARCHETYPE},
+        decal::Decal,
+        door::Door,
+        alarm::Alarm,
+        pickup::PICKUPS,
+        weapon::WEAPONS,
+    },
+};
+
+use serde::Serialize;
+
+use super::{Level, Grid, Palette, LevelError};
+
+/// Characters used for the materials of the palette in the rows of the grid
+const TILES: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// A level in a form that's nice to read and diff as TOML
+///
+/// Values have to come before tables in TOML so the order of the fields matters.
+#[derive(Debug, Serialize, Deserialize)]
+struct TextLevel {
+    palette: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<(f32, f32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit: Option<(f32, f32)>,
+    #[serde(default)]
+    intels: Vec<(f32, f32)>,
+    /// One line per row of tiles, each character being the index of a material in the palette
+    grid: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    enemies: Vec<TextEnemy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pickups: Vec<TextPickup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    decals: Vec<TextDecal>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    weapons: Vec<TextWeapon>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    doors: Vec<TextDoor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    alarms: Vec<TextAlarm>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextEnemy {
+    pos: (f32, f32),
+    #[serde(default)]
+    rot: f32,
+    /// Id of the enemy's archetype
+    #[serde(default = "default_kind")]
+    kind: String,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    grenades: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    patrol: Vec<TextWaypoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextWaypoint {
+    pos: (f32, f32),
+    #[serde(default)]
+    wait: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    facing: Option<f32>,
+}
+
+fn default_kind() -> String {
+    DEFAULT_ARCHETYPE.to_owned()
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_zero(n: &u8) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextPickup {
+    pos: (f32, f32),
+    /// Name of the pickup's sprite without the directory
+    kind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextDecal {
+    pos: (f32, f32),
+    #[serde(default)]
+    rot: f32,
+    spr: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextWeapon {
+    pos: (f32, f32),
+    id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextDoor {
+    pos: (f32, f32),
+    #[serde(default)]
+    rot: f32,
+    #[serde(default)]
+    open: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextAlarm {
+    pos: (f32, f32),
+    #[serde(default)]
+    rot: f32,
+}
+
+fn pickup_kind(i: u8) -> &'static str {
+    let spr = PICKUPS[i as usize].spr;
+    spr.rsplit('/').next().unwrap_or(spr)
+}
+
+/// Turns a level into its TOML representation
+pub fn level_to_text(level: &Level) -> Result<String, LevelError> {
+    if level.palette.len() >= TILES.len() {
+        return Err(LevelError::Text(format!("palette has {} materials but at most {} can be written as text", level.palette.len(), TILES.len() - 1)));
+    }
+    let palette: Vec<String> = level.palette.iter().map(ToOwned::to_owned).collect();
+
+    let mut grid = String::with_capacity(level.grid.mats.len() + level.grid.height() as usize);
+    for (y, row) in level.grid.mats.chunks(level.grid.width.max(1) as usize).enumerate() {
+        for (x, &m) in row.iter().enumerate() {
+            if m as usize >= palette.len() {
+                return Err(LevelError::Text(format!("material {} at tile ({}, {}) isn't in the palette", m, x, y)));
+            }
+            grid.push(TILES[m as usize] as char);
+        }
+        grid.push('\n');
+    }
+
+    let text = TextLevel {
+        palette,
+        start: level.start_point.map(|p| (p.x, p.y)),
+        exit: level.exit.map(|p| (p.x, p.y)),
+        intels: level.intels.iter().map(|p| (p.x, p.y)).collect(),
+        grid,
+        enemies: level.enemies.iter().map(|e| TextEnemy {
+            pos: (e.pl.obj.pos.x, e.pl.obj.pos.y),
+            rot: e.pl.obj.rot,
+            kind: e.kind.id.to_owned(),
+            grenades: e.pl.wep.utilities.grenades,
+            patrol: e.patrol.route.iter().map(|w| TextWaypoint {
+                pos: (w.pos.x, w.pos.y),
+                wait: w.wait,
+                facing: w.facing,
+            }).collect(),
+        }).collect(),
+        pickups: level.pickups.iter().map(|&(p, i)| TextPickup {
+            pos: (p.x, p.y),
+            kind: pickup_kind(i).to_owned(),
+        }).collect(),
+        decals: level.decals.iter().map(|d| TextDecal {
+            pos: (d.obj.pos.x, d.obj.pos.y),
+            rot: d.obj.rot,
+            spr: d.spr.to_owned(),
+        }).collect(),
+        weapons: level.weapons.iter().map(|w| TextWeapon {
+            pos: (w.pos.x, w.pos.y),
+            id: w.weapon.id.to_owned(),
+        }).chain(level.unknown_weapons.iter().map(|(p, id)| TextWeapon {
+            pos: (p.x, p.y),
+            id: id.clone(),
+        })).collect(),
+        doors: level.doors.iter().map(|d| TextDoor {
+            pos: (d.obj.pos.x, d.obj.pos.y),
+            rot: d.obj.rot,
+            open: d.open,
+        }).collect(),
+        alarms: level.alarms.iter().map(|a| TextAlarm {
+            pos: (a.obj.pos.x, a.obj.pos.y),
+            rot: a.obj.rot,
+        }).collect(),
+    };
+
+    let mut s = String::new();
+    text.serialize(toml::Serializer::new(&mut s).pretty_string(true))
+        .map_err(|e| LevelError::Text(e.to_string()))?;
+    Ok(s)
+}
+
+/// Reads a level from its TOML representation
+pub fn level_from_text(s: &str) -> Result<Level, LevelError> {
+    let text: TextLevel = toml::from_str(s).map_err(|e| LevelError::Text(e.to_string()))?;
+
+    let rows: Vec<_> = text.grid.lines().map(str::trim).filter(|row| !row.is_empty()).collect();
+    let width = rows.first().map(|row| row.len()).unwrap_or(0);
+    if width == 0 {
+        return Err(LevelError::Text("the grid is empty".to_owned()));
+    }
+    if width > std::u16::MAX as usize || rows.len() > std::u16::MAX as usize {
+        return Err(LevelError::Text(format!("the grid is {}x{} tiles but can be at most {2}x{2}", width, rows.len(), std::u16::MAX)));
+    }
+    let mut mats = Vec::with_capacity(width * rows.len());
+    for (y, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(LevelError::Text(format!("row {} of the grid is {} tiles wide instead of {}", y, row.len(), width)));
+        }
+        for (x, c) in row.bytes().enumerate() {
+            match TILES.iter().position(|&t| t == c) {
+                Some(m) if m < text.palette.len() => mats.push(m as u8),
+                _ => return Err(LevelError::Text(format!("unknown material `{}' at tile ({}, {})", c as char, x, y))),
+            }
+        }
+    }
+
+    let point = |(x, y)| Point2::new(x, y);
+
+    let mut weapons = Vec::new();
+    let mut unknown_weapons = Vec::new();
+    for w in text.weapons {
+        match WEAPONS.get(&*w.id) {
+            Some(wep) => weapons.push(wep.make_drop(point(w.pos))),
+            None => unknown_weapons.push((point(w.pos), w.id)),
+        }
+    }
+
+    Ok(Level {
+        palette: Palette::new(text.palette.into_iter().map(sstr).collect()),
+        grid: Grid {
+            width: width as u16,
+            mats,
+            closed_doors: Default::default(),
+        },
+        start_point: text.start.map(point),
+        exit: text.exit.map(point),
+        intels: text.intels.into_iter().map(point).collect(),
+        enemies: text.enemies.into_iter()
+            .map(|e| ARCHETYPES.get(&*e.kind)
+                .map(|kind| {
+                    let mut enemy = Enemy {
+                        patrol: Patrol::new(e.patrol.iter().map(|w| Waypoint {
+                            pos: point(w.pos),
+                            wait: w.wait,
+                            facing: w.facing,
+                        }).collect()),
+                        .. Enemy::new(Object::with_rot(point(e.pos), e.rot), kind)
+                    };
+                    enemy.pl.wep.utilities.grenades = e.grenades;
+                    enemy
+                })
+                .ok_or_else(|| LevelError::Text(format!("no enemy type called `{}'", e.kind))))
+            .collect::<Result<_, _>>()?,
+        pickups: text.pickups.into_iter()
+            .map(|p| (0..PICKUPS.len() as u8)
+                .find(|&i| pickup_kind(i) == p.kind)
+                .map(|i| (point(p.pos), i))
+                .ok_or_else(|| LevelError::Text(format!("no pickup called `{}'", p.kind))))
+            .collect::<Result<_, _>>()?,
+        decals: text.decals.into_iter()
+            .map(|d| Decal::new(Object::with_rot(point(d.pos), d.rot), sstr(d.spr)))
+            .collect(),
+        weapons,
+        unknown_weapons,
+        doors: text.doors.into_iter()
+            .map(|d| Door {
+                obj: Object::with_rot(point(d.pos), d.rot),
+                open: d.open,
+            })
+            .collect(),
+        alarms: text.alarms.into_iter()
+            .map(|a| Alarm::new(Object::with_rot(point(a.pos), a.rot)))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::format::write_level;
+
+    use std::fs;
+
+    /// Going through the current binary format as well so both ways of reading a level are compared
+    #[test]
+    fn levels_round_trip() {
+        for entry in fs::read_dir("levels").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map(|e| e != "lvl").unwrap_or(true) {
+                continue;
+            }
+            let mut bytes = Vec::new();
+            write_level(&Level::load(&path).unwrap(), &mut bytes).unwrap();
+            let level = Level::from_bytes(&bytes).unwrap();
+            let text = level_to_text(&level).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            let reread = level_from_text(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+            assert_eq!(level, reread, "{} changed going through text", path.display());
+        }
+    }
+
+    #[test]
+    fn every_enemy_kind_round_trips() {
+        let mut level = Level::new(Palette::default(), 4, 4);
+        let mut ids: Vec<_> = ARCHETYPES.keys().collect();
+        ids.sort();
+        for (i, id) in ids.into_iter().enumerate() {
+            let mut enemy = Enemy::new(Object::new(Point2::new(16. + 32. * i as f32, 16.)), &ARCHETYPES[id]);
+            enemy.pl.wep.utilities.grenades = i as u8;
+            level.enemies.push(enemy);
+        }
+
+        let mut bytes = Vec::new();
+        write_level(&level, &mut bytes).unwrap();
+        let from_bytes = Level::from_bytes(&bytes).unwrap();
+        let from_text = level_from_text(&level_to_text(&level).unwrap()).unwrap();
+
+        assert_eq!(level, from_bytes);
+        assert_eq!(level, from_text);
+    }
+
+    fn text_error(text: &str) {
+        match level_from_text(text) {
+            Err(LevelError::Text(_)) => (),
+            r => panic!("expected a text error, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn empty_grid() {
+        text_error("palette = [\"wall\"]\ngrid = \"\"");
+        text_error("palette = [\"wall\"]\ngrid = \"\"\"\n\n\"\"\"");
+    }
+
+    #[test]
+    fn too_wide_grid() {
+        text_error(&format!("palette = [\"wall\"]\ngrid = \"{}\"", "0".repeat(std::u16::MAX as usize + 1)));
+    }
+
+    #[test]
+    fn too_big_palette() {
+        let mats: Vec<_> = (0..TILES.len()).map(|i| sstr(format!("mat{}", i))).collect();
+        let level = Level::new(Palette::new(mats), 1, 1);
+
+        assert!(level_to_text(&level).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use crate::util::Point2;
+
+use super::{Level, Grid};
+
+/// How close a weapon has to be to an enemy for the enemy to start with it
+const PICKUP_RANGE: f32 = 16.;
+
+/// Something in a level that has a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Thing {
+    Start,
+    Exit,
+    Intel,
+    Pickup,
+    Weapon,
+    Alarm,
+    /// A point on an enemy's patrol route
+    Waypoint,
+}
+
+impl Display for Thing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Thing::*;
+        match *self {
+            Start => "Start",
+            Exit => "Exit",
+            Intel => "Intel",
+            Pickup => "Pickup",
+            Weapon => "Weapon",
+            Alarm => "Alarm",
+            Waypoint => "Patrol waypoint",
+        }.fmt(f)
+    }
+}
+
+/// A problem with a level
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The level has no starting point for the player
+    NoStart,
+    /// Something is inside a solid tile
+    InWall(Thing),
+    /// Something the player has to reach to win can't be walked to from the start
+    Unreachable(Thing),
+    /// An enemy has no weapon close enough to start with
+    UnarmedEnemy,
+    UnknownWeapon(String),
+    UnknownDecal(String),
+}
+
+impl Problem {
+    /// Whether the level can't be played properly because of the problem
+    pub fn is_error(&self) -> bool {
+        match *self {
+            Problem::UnarmedEnemy => false,
+            _ => true,
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Problem::*;
+        match self {
+            NoStart => write!(f, "No start point"),
+            InWall(thing) => write!(f, "{} is inside a wall", thing),
+            Unreachable(thing) => write!(f, "{} can't be reached from the start", thing),
+            UnarmedEnemy => write!(f, "Enemy has no weapon"),
+            UnknownWeapon(id) => write!(f, "Unknown weapon `{}'", id),
+            UnknownDecal(spr) => write!(f, "Unknown decal sprite `{}'", spr),
+        }
+    }
+}
+
+/// A problem found in a level and where it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub problem: Problem,
+    pub pos: Option<Point2>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pos {
+            Some(p) => write!(f, "{} at ({:.0}, {:.0})", self.problem, p.x, p.y),
+            None => self.problem.fmt(f),
+        }
+    }
+}
+
+impl Level {
+    /// Checks the level for problems that would make it unplayable or not completable
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |problem, pos| diagnostics.push(Diagnostic{problem, pos});
+
+        let in_wall = |p: Point2| {
+            let (x, y) = Grid::snap(p);
+            self.grid.is_wall(&self.palette, x, y)
+        };
+
+        // Hand out weapons the same way `World::enemy_pickup` does
+        let mut taken = vec![false; self.weapons.len()];
+        for enemy in &self.enemies {
+            let pos = enemy.pl.obj.pos;
+            let weapon = (0..self.weapons.len())
+                .find(|&i| !taken[i] && (self.weapons[i].pos - pos).norm() <= PICKUP_RANGE);
+
+            match weapon {
+                Some(i) => taken[i] = true,
+                None => report(Problem::UnarmedEnemy, Some(pos)),
+            }
+        }
+
+        let mut placed = Vec::new();
+        placed.extend(self.start_point.map(|p| (Thing::Start, p)));
+        placed.extend(self.exit.map(|p| (Thing::Exit, p)));
+        placed.extend(self.intels.iter().map(|&p| (Thing::Intel, p)));
+        placed.extend(self.pickups.iter().map(|&(p, _)| (Thing::Pickup, p)));
+        // Weapons enemies start with don't need to be reachable
+        placed.extend(self.weapons.iter().zip(&taken).filter(|&(_, &t)| !t).map(|(w, _)| (Thing::Weapon, w.pos)));
+        placed.extend(self.alarms.iter().map(|a| (Thing::Alarm, a.obj.pos)));
+        placed.extend(self.enemies.iter().flat_map(|e| &e.patrol.route).map(|w| (Thing::Waypoint, w.pos)));
+
+        for &(thing, p) in &placed {
+            if in_wall(p) {
+                report(Problem::InWall(thing), Some(p));
+            }
+        }
+
+        if let Some(start) = self.start_point {
+            let reachable = self.reachable_from(Grid::snap(start));
+            let goals = self.exit.iter().map(|&p| (Thing::Exit, p))
+                .chain(self.intels.iter().map(|&p| (Thing::Intel, p)));
+
+            for (thing, p) in goals {
+                let (x, y) = Grid::snap(p);
+                let reached = x < self.grid.width() && reachable.get(self.grid.idx(x, y)).cloned().unwrap_or(false);
+                if !reached && !in_wall(p) {
+                    report(Problem::Unreachable(thing), Some(p));
+                }
+            }
+        } else {
+            report(Problem::NoStart, None);
+        }
+
+        for (p, id) in &self.unknown_weapons {
+            report(Problem::UnknownWeapon(id.clone()), Some(*p));
+        }
+        for decal in &self.decals {
+            if !Path::new("resources").join(format!("{}.png", decal.spr)).is_file() {
+                report(Problem::UnknownDecal(decal.spr.to_owned()), Some(decal.obj.pos));
+            }
+        }
+
+        diagnostics
+    }
+    /// Which tiles can be walked to from a given tile, indexed like the grid
+    ///
+    /// Doors are treated as if they were open.
+    fn reachable_from(&self, start: (u16, u16)) -> Vec<bool> {
+        let grid = &self.grid;
+        let mut reachable = vec![false; grid.mats.len()];
+        if grid.is_wall(&self.palette, start.0, start.1) {
+            return reachable;
+        }
+
+        let mut queue = VecDeque::new();
+        reachable[grid.idx(start.0, start.1)] = true;
+        queue.push_back(start);
+
+        while let Some((x, y)) = queue.pop_front() {
+            let neighbours = [
+                (x.checked_sub(1), Some(y)),
+                (x.checked_add(1), Some(y)),
+                (Some(x), y.checked_sub(1)),
+                (Some(x), y.checked_add(1)),
+            ];
+            for &n in &neighbours {
+                if let (Some(nx), Some(ny)) = n {
+                    if grid.is_wall(&self.palette, nx, ny) {
+                        continue;
+                    }
+                    let i = grid.idx(nx, ny);
+                    if !reachable[i] {
+                        reachable[i] = true;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::level_from_text;
+
+    #[test]
+    fn unknown_weapon_is_reported() {
+        let level = level_from_text(r#"
+            palette = ["floor"]
+            start = [16.0, 16.0]
+            grid = """
+            00
+            00
+            """
+
+            [[weapons]]
+            pos = [48.0, 48.0]
+            id = "raygun"
+        "#).unwrap();
+
+        let unknown = Diagnostic {
+            problem: Problem::UnknownWeapon("raygun".to_owned()),
+            pos: Some(Point2::new(48., 48.)),
+        };
+        assert!(level.validate().contains(&unknown));
+    }
+}
@@ -2,17 +2,20 @@ use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use crate::util::{Vector2, Point2};
 
 /// Serialize a `Vector2`
-#[allow(dead_code)]
 #[allow(clippy::trivially_copy_pass_by_ref)]
 pub fn vec_ser<S: Serializer>(p: &Vector2, ser: S) -> Result<S::Ok, S::Error> {
     (p.x, p.y).serialize(ser)
 }
 /// Deserialize a `Vector2`
-#[allow(dead_code)]
 pub fn vec_des<'de, D: Deserializer<'de>>(des: D) -> Result<Vector2, D::Error> {
     <(f32, f32)>::deserialize(des).map(|(x, y)| Vector2::new(x, y))
 }
 
+/// 64-bit FNV-1a hash of some bytes
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+}
+
 #[inline]
 #[allow(clippy::trivially_copy_pass_by_ref, dead_code)]
 fn p(p: &Point2) -> (f32, f32) {
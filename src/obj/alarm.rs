@@ -0,0 +1,37 @@
+use crate::{
+    util::Point2,
+    io::tex::{Assets, },
+};
+use ggez::{Context, GameResult, graphics::Color};
+
+use super::Object;
+
+/// How close an enemy has to be to an alarm to raise it
+pub const ALARM_REACH: f32 = 40.;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Something enemies can raise to alert everyone in the level
+pub struct Alarm {
+    pub obj: Object,
+    #[serde(skip)]
+    pub raised: bool,
+}
+
+impl Alarm {
+    #[inline]
+    pub fn new(obj: Object) -> Self {
+        Alarm {
+            obj,
+            raised: false,
+        }
+    }
+    #[inline]
+    pub fn in_reach(&self, p: Point2) -> bool {
+        (self.obj.pos - p).norm() <= ALARM_REACH
+    }
+    #[inline]
+    pub fn draw(&self, ctx: &mut Context, a: &Assets, color: Color) -> GameResult<()> {
+        let img = a.get_img(ctx, if self.raised { "common/alarm_on" } else { "common/alarm" });
+        self.obj.draw(ctx, &*img, color)
+    }
+}
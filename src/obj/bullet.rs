@@ -1,34 +1,71 @@
-use ggez::{Context, GameResult, graphics::WHITE};
+use ggez::{Context, GameResult, graphics::{WHITE, Color, MeshBuilder}};
+use rand::Rng;
 
 use crate::{
-    util::{Vector2},
+    util::{Vector2, Point2, angle_from_vec},
     game::{
         DELTA,
         world::{Grid, Palette},
     },
     io::tex::{Assets, }
 };
-use super::{Object, player::Player, enemy::Enemy, health::Health, weapon::Weapon};
+use super::{Object, player::Player, enemy::Enemy, weapon::Weapon};
+
+/// Fraction of its speed a bullet keeps when glancing off a wall
+const RICOCHET_SPEED: f32 = 0.6;
+/// Sine of the largest angle to a wall a bullet can glance off it at
+const RICOCHET_ANGLE_SIN: f32 = 0.5;
+/// Penetration power a bullet needs to go through a closed door
+const DOOR_RESISTANCE: f32 = 25.;
+/// Speed a bullet has to be going for its tracer to show
+const TRACER_SPEED: f32 = 600.;
+/// Number of pieces a tracer is drawn in to fade it out
+const TRACER_SEGMENTS: u8 = 4;
 
 #[derive(Debug, Clone)]
 pub struct Bullet<'a> {
     pub obj: Object,
     pub weapon: &'a Weapon,
     pub vel: Vector2,
+    /// Damage at full speed, going down as pellets travel
+    pub damage: f32,
+    /// Where the bullet was fired from or last glanced off a wall
+    pub origin: Point2,
 }
 
 impl Bullet<'_> {
-    pub fn apply_damage(&self, health: &mut Health) {
-        let dmg = self.weapon.damage * self.vel.norm() / self.weapon.bullet_speed;
+    pub fn apply_damage(&self, pl: &mut Player) {
+        let dmg = self.damage * self.vel.norm() / self.weapon.bullet_speed;
 
-        health.weapon_damage(dmg, self.weapon.penetration);
+        pl.weapon_damage(dmg, self.weapon.penetration);
     }
     #[inline]
     pub fn draw(&self, ctx: &mut Context, a: &Assets) -> GameResult<()> {
-        let img = a.get_img(ctx, self.weapon.get_bullet_spr());
+        let img = a.get_img(ctx, self.weapon.bullet_sprite);
         self.obj.draw(ctx, &*img, WHITE)
     }
-    pub fn update(&mut self, palette: &Palette, grid: &Grid, player: &mut Player, enemies: &mut [Enemy]) -> Hit {
+    /// Adds the bullet's tracer to `builder` if it has one and is going fast enough, returning whether it did
+    pub fn add_tracer(&self, builder: &mut MeshBuilder) -> GameResult<bool> {
+        let speed = self.vel.norm();
+        if let Some(tracer) = self.weapon.tracer {
+            let length = (tracer.length * speed / self.weapon.bullet_speed).min((self.obj.pos - self.origin).norm());
+            if speed < TRACER_SPEED || length < 1. {
+                return Ok(false);
+            }
+            let back = -length / f32::from(TRACER_SEGMENTS) * self.vel / speed;
+
+            // Fade out towards the tail
+            for i in 0..TRACER_SEGMENTS {
+                let start = self.obj.pos + f32::from(i) * back;
+                let a = tracer.color.a * f32::from(TRACER_SEGMENTS - i) / f32::from(TRACER_SEGMENTS);
+                builder.line(&[start, start + back], 2., Color{a, .. tracer.color})?;
+            }
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+    pub fn update<R: Rng>(&mut self, palette: &Palette, grid: &Grid, player: &mut Player, enemies: &mut [Enemy], rng: &mut R) -> Hit {
         let start = self.obj.pos;
         let d_pos = self.vel * DELTA;
 
@@ -40,12 +77,12 @@ impl Bullet<'_> {
         
         // Check if we've hit a player or an enemy
         if Grid::dist_line_circle(start, d_pos, player.obj.pos) <= 16. {
-            self.apply_damage(&mut player.health);
+            self.apply_damage(player);
             return Hit::Player;
         }
         for (i, enem) in enemies.iter_mut().enumerate() {
             if Grid::dist_line_circle(start, d_pos, enem.pl.obj.pos) <= 16. {
-                self.apply_damage(&mut enem.pl.health);
+                self.apply_damage(&mut enem.pl);
                 return Hit::Enemy(i);
             }
         }
@@ -56,18 +93,183 @@ impl Bullet<'_> {
         // Ray cast bullet to see if we've hit a wall and move bullet accordingly
         let cast = grid.ray_cast(palette, start, d_pos, true);
         self.obj.pos = cast.into_point();
-        if cast.full() {
-            Hit::None
+        if let Some(spread) = self.weapon.spread {
+            self.damage *= 0.5f32.powf((self.obj.pos - start).norm() / spread.falloff);
+        }
+        match cast.half_vec() {
+            // Already inside a wall it's gone into
+            Some(to_wall) if to_wall.norm_squared() == 0. => self.hit_wall(self.vel.normalize(), palette, grid, rng),
+            Some(to_wall) => self.hit_wall(to_wall.normalize(), palette, grid, rng),
+            None if cast.full() => Hit::None,
+            None => Hit::Wall,
+        }
+    }
+    /// Sends the bullet through or off the wall it has hit going in the `to_wall` direction if it can
+    fn hit_wall<R: Rng>(&mut self, to_wall: Vector2, palette: &Palette, grid: &Grid, rng: &mut R) -> Hit {
+        let hole = Object::with_rot(self.obj.pos + 5. * to_wall, self.obj.rot);
+        let inside = self.obj.pos + 0.5 * to_wall;
+        let (gx, gy) = Grid::snap(inside);
+
+        let (resistance, ricochet) = if grid.is_closed_door(gx, gy) {
+            (DOOR_RESISTANCE, 0.)
+        } else if let Some(mat) = grid.get(gx, gy) {
+            (palette.resistance(mat), palette.ricochet(mat))
+        } else {
+            return Hit::Wall;
+        };
+        let speed = self.vel.norm();
+        // Slower bullets punch through less
+        let power = self.weapon.penetration_power * speed / self.weapon.bullet_speed;
+
+        if resistance < power {
+            self.vel *= 1. - resistance / power;
+            self.obj.pos = tile_exit(inside, self.vel, gx, gy);
+            Hit::Through(hole)
+        } else if ricochet > 0. && self.vel.dot(&to_wall) < RICOCHET_ANGLE_SIN * speed && rng.gen::<f32>() < ricochet {
+            self.vel = RICOCHET_SPEED * (self.vel - 2. * self.vel.dot(&to_wall) * to_wall);
+            self.obj.rot = angle_from_vec(self.vel);
+            self.obj.pos -= to_wall;
+            self.origin = self.obj.pos;
+            Hit::Ricochet(hole)
         } else {
             Hit::Wall
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Where something at `p` going in the direction of `vel` leaves the tile at `gx`, `gy`
+fn tile_exit(p: Point2, vel: Vector2, gx: u16, gy: u16) -> Point2 {
+    let exit_time = |p: f32, v: f32, g: u16| {
+        let start = f32::from(g) * 32.;
+        if v > 0. {
+            (start + 32. - p) / v
+        } else if v < 0. {
+            (start - p) / v
+        } else {
+            std::f32::INFINITY
+        }
+    };
+    let time = exit_time(p.x, vel.x, gx).min(exit_time(p.y, vel.y, gy));
+
+    // Nudged past the edge so it's in the next tile
+    p + time * vel + 0.5 * vel.normalize()
+}
+
+#[derive(Debug, Clone)]
 pub enum Hit {
     Wall,
+    /// Went through a wall, leaving a hole as the given object
+    Through(Object),
+    /// Glanced off a wall, leaving a hole as the given object
+    Ricochet(Object),
     Player,
     Enemy(usize),
     None,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::weapon::WEAPONS;
+    use rand::rngs::mock::StepRng;
+
+    /// Floor on both sides of a single tile of `mat` in the middle
+    fn wall_of(mat: &'static str) -> (Palette, Grid) {
+        let palette = Palette::new(vec!["floor", mat]);
+        let mut grid = Grid::new(3, 1);
+        grid.insert(1, 0, 1);
+        (palette, grid)
+    }
+
+    /// A bullet from `weapon` fired from `from` in the direction of `dir`
+    fn bullet(weapon: &str, from: Point2, dir: Vector2) -> Bullet<'static> {
+        let weapon = &WEAPONS[weapon];
+        Bullet {
+            obj: Object::with_rot(from, angle_from_vec(dir)),
+            vel: weapon.bullet_speed * dir.normalize(),
+            damage: weapon.damage,
+            origin: from,
+            weapon,
+        }
+    }
+
+    fn shoot(bullet: &mut Bullet, palette: &Palette, grid: &Grid) -> Hit {
+        let mut player = Player::from_point(Point2::new(1000., 1000.));
+        // Rolls 0 every time so anything that can ricochet does
+        let mut rng = StepRng::new(0, 0);
+        bullet.update(palette, grid, &mut player, &mut [], &mut rng)
+    }
+
+    #[test]
+    fn tile_exit_is_in_the_next_tile() {
+        assert_eq!(Grid::snap(tile_exit(Point2::new(33., 16.), Vector2::new(1., 0.), 1, 0)), (2, 0));
+        assert_eq!(Grid::snap(tile_exit(Point2::new(63., 16.), Vector2::new(-1., 0.), 1, 0)), (0, 0));
+        assert_eq!(Grid::snap(tile_exit(Point2::new(48., 33.), Vector2::new(0., 1.), 1, 1)), (1, 2));
+
+        let exit = tile_exit(Point2::new(40., 40.), Vector2::new(1., 1.), 1, 1);
+        assert_eq!(Grid::snap(exit), (2, 2));
+        assert!((exit.x - exit.y).abs() < 0.01, "left the diagonal at {:?}", exit);
+    }
+
+    #[test]
+    fn powerful_bullet_goes_through_wood() {
+        let (palette, grid) = wall_of("wood_wall");
+        let mut bullet = bullet("magnum", Point2::new(16., 16.), Vector2::new(1., 0.));
+        let speed = bullet.vel.norm();
+
+        match shoot(&mut bullet, &palette, &grid) {
+            Hit::Through(_) => (),
+            hit => panic!("expected the bullet to go through, got {:?}", hit),
+        }
+        assert_eq!(Grid::snap(bullet.obj.pos), (2, 0));
+        assert!(bullet.vel.norm() < speed, "bullet didn't slow down going through the wall");
+        assert!(bullet.vel.x > 0. && bullet.vel.y == 0.);
+    }
+
+    #[test]
+    fn weak_bullet_stops_in_wood() {
+        let (palette, grid) = wall_of("wood_wall");
+        let mut bullet = bullet("glock", Point2::new(16., 16.), Vector2::new(1., 0.));
+
+        match shoot(&mut bullet, &palette, &grid) {
+            Hit::Wall => (),
+            hit => panic!("expected the bullet to stop, got {:?}", hit),
+        }
+    }
+
+    #[test]
+    fn glancing_bullet_reflects_off_wall() {
+        let palette = Palette::new(vec!["floor", "wall"]);
+        let mut grid = Grid::new(8, 2);
+        for x in 0..8 {
+            grid.insert(x, 0, 1);
+        }
+        // Going right and up into the wall above at a shallow angle
+        let dir = Vector2::new(1., -0.2);
+        let mut bullet = bullet("glock", Point2::new(16., 34.), dir);
+        let speed = bullet.vel.norm();
+
+        match shoot(&mut bullet, &palette, &grid) {
+            Hit::Ricochet(_) => (),
+            hit => panic!("expected a ricochet, got {:?}", hit),
+        }
+        // Mirrored in the wall's normal, losing speed
+        let expected = Vector2::new(1., 0.2).normalize();
+        assert!((bullet.vel.normalize() - expected).norm() < 0.001, "went off at {:?} instead of {:?}", bullet.vel, expected);
+        assert!(bullet.vel.norm() <= RICOCHET_SPEED * speed);
+        assert!(bullet.obj.pos.y > 32., "stuck in the wall at {:?}", bullet.obj.pos);
+    }
+
+    #[test]
+    fn head_on_bullet_does_not_ricochet() {
+        let palette = Palette::new(vec!["floor", "wall"]);
+        let mut grid = Grid::new(2, 2);
+        grid.insert(0, 0, 1);
+        grid.insert(1, 0, 1);
+        let mut bullet = bullet("glock", Point2::new(16., 48.), Vector2::new(0., -1.));
+
+        match shoot(&mut bullet, &palette, &grid) {
+            Hit::Wall => (),
+            hit => panic!("expected the bullet to stop, got {:?}", hit),
+        }
+    }
+}
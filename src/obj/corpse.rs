@@ -0,0 +1,54 @@
+use crate::{
+    util::{Sstr, Point2},
+    io::tex::{Assets, },
+    game::{DELTA, world::{Grid, Palette}},
+};
+use ggez::{Context, GameResult, graphics::Color};
+
+use super::Object;
+
+/// How close the player has to be to a body to drag it
+pub const DRAG_REACH: f32 = 40.;
+/// How far behind the player a dragged body trails
+pub const DRAG_DISTANCE: f32 = 24.;
+
+const CORPSE_COLOR: Color = Color{r: 0.45, g: 0.4, b: 0.4, a: 1.};
+
+#[derive(Debug, Clone)]
+/// The body of a dead enemy
+pub struct Corpse {
+    pub obj: Object,
+    pub spr: Sstr,
+    /// Whether an enemy has already found it
+    pub discovered: bool,
+}
+
+impl Corpse {
+    #[inline]
+    pub fn new(obj: Object, spr: Sstr) -> Self {
+        Corpse {
+            obj,
+            spr,
+            discovered: false,
+        }
+    }
+    #[inline]
+    pub fn in_reach(&self, p: Point2) -> bool {
+        (self.obj.pos - p).norm() <= DRAG_REACH
+    }
+    /// Moves the body along after someone dragging it at `p` without pulling it through walls.
+    /// Returns whether it's still in reach, i.e. it hasn't gotten stuck on a corner
+    pub fn drag(&mut self, p: Point2, palette: &Palette, grid: &Grid) -> bool {
+        let dist = p - self.obj.pos;
+        let slack = dist.norm() - DRAG_DISTANCE;
+        if slack > 0. {
+            self.obj.move_on_grid(dist.normalize(), slack / DELTA, palette, grid);
+        }
+        self.in_reach(p)
+    }
+    #[inline]
+    pub fn draw(&self, ctx: &mut Context, a: &Assets) -> GameResult<()> {
+        let img = a.get_img(ctx, self.spr);
+        self.obj.draw(ctx, &*img, CORPSE_COLOR)
+    }
+}
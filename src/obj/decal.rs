@@ -41,7 +41,7 @@ const OLD_DECORATION_LIST: [&str; 15] = [
     "decorations/road_mark"
 ];
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Decal {
     pub obj: Object,
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
@@ -0,0 +1,42 @@
+use crate::{
+    util::Point2,
+    io::tex::{Assets, },
+    game::world::{Grid, tile_centre},
+};
+use ggez::{Context, GameResult, graphics::Color};
+
+use super::Object;
+
+/// How close something has to be to the centre of a door to open or close it
+pub const DOOR_REACH: f32 = 40.;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A door filling a single tile that blocks everything while closed
+pub struct Door {
+    /// Centered on the tile the door is in
+    pub obj: Object,
+    pub open: bool,
+}
+
+impl Door {
+    pub fn new(x: u16, y: u16, rot: f32) -> Self {
+        Door {
+            obj: Object::with_rot(tile_centre(x, y), rot),
+            open: false,
+        }
+    }
+    /// The grid coordinates of the tile the door is in
+    #[inline]
+    pub fn tile(&self) -> (u16, u16) {
+        Grid::snap(self.obj.pos)
+    }
+    #[inline]
+    pub fn in_reach(&self, p: Point2) -> bool {
+        (self.obj.pos - p).norm() <= DOOR_REACH
+    }
+    #[inline]
+    pub fn draw(&self, ctx: &mut Context, a: &Assets, color: Color) -> GameResult<()> {
+        let img = a.get_img(ctx, if self.open { "common/door_open" } else { "common/door" });
+        self.obj.draw(ctx, &*img, color)
+    }
+}
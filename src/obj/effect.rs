@@ -0,0 +1,89 @@
+use ggez::{Context, GameResult, graphics::WHITE};
+use rand::Rng;
+
+use crate::{
+    util::{Sstr, Vector2, angle_to_vec},
+    io::tex::{Assets, },
+    game::{DELTA, world::{Grid, Palette}},
+};
+
+use super::{Object, decal::Decal};
+
+/// Seconds a muzzle flash is shown for
+const MUZZLE_FLASH_TIME: f32 = 0.05;
+/// Seconds a casing flies before landing
+const CASING_TIME: f32 = 0.35;
+/// Fraction of its speed a flying casing keeps each second
+const CASING_DRAG: f32 = 0.02;
+
+#[derive(Debug, Clone)]
+/// Something short-lived that's only there for show, like a muzzle flash or a casing flying out of a gun
+pub struct Effect {
+    pub obj: Object,
+    pub spr: Sstr,
+    vel: Vector2,
+    spin: f32,
+    /// Seconds left until it's over
+    time: f32,
+    /// Whether it's left on the ground afterwards
+    lands: bool,
+}
+
+impl Effect {
+    /// A flash in front of a gun fired from `muzzle`
+    pub fn muzzle_flash(muzzle: &Object, spr: Sstr) -> Self {
+        let pos = muzzle.pos + 8. * angle_to_vec(muzzle.rot);
+
+        Effect {
+            obj: Object::with_rot(pos, muzzle.rot),
+            spr,
+            vel: Vector2::new(0., 0.),
+            spin: 0.,
+            time: MUZZLE_FLASH_TIME,
+            lands: false,
+        }
+    }
+    /// A casing thrown out to the right of a gun fired from `muzzle`
+    pub fn casing<R: Rng>(muzzle: &Object, spr: Sstr, rng: &mut R) -> Self {
+        let right = muzzle.rot + std::f32::consts::FRAC_PI_2 + rng.gen_range(-0.3, 0.3);
+        let pos = muzzle.pos - 12. * angle_to_vec(muzzle.rot);
+
+        Effect {
+            obj: Object::with_rot(pos, muzzle.rot),
+            spr,
+            vel: rng.gen_range(140., 220.) * angle_to_vec(right),
+            spin: rng.gen_range(-20., 20.),
+            time: CASING_TIME,
+            lands: true,
+        }
+    }
+    /// Moves the effect along, returning whether it's over
+    pub fn update(&mut self, palette: &Palette, grid: &Grid) -> bool {
+        let last_pos = self.obj.pos;
+        self.obj.pos += self.vel * DELTA;
+        if self.obj.is_on_solid(palette, grid) {
+            // Bounced off a wall and fell down
+            self.obj.pos = last_pos;
+            self.vel = Vector2::new(0., 0.);
+        }
+        self.vel *= CASING_DRAG.powf(DELTA);
+        self.obj.rot += self.spin * DELTA;
+        self.time -= DELTA;
+
+        self.time <= 0.
+    }
+    /// What's left on the ground once the effect is over
+    pub fn into_decal(self) -> Option<Decal> {
+        let Effect{obj, spr, lands, ..} = self;
+        if lands {
+            Some(Decal::new(obj, spr))
+        } else {
+            None
+        }
+    }
+    #[inline]
+    pub fn draw(&self, ctx: &mut Context, a: &Assets) -> GameResult<()> {
+        let img = a.get_img(ctx, self.spr);
+        self.obj.draw(ctx, &*img, WHITE)
+    }
+}
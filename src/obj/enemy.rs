@@ -2,88 +2,125 @@ use crate::util::{BLUE, Vector2, Point2};
 
 use ggez::{
     Context, GameResult,
-    graphics::{self, Mesh, Color, DrawMode, DrawParam},
+    graphics::{self, MeshBuilder, Color, DrawMode, DrawParam},
 };
 
 use crate::{
     util::{angle_from_vec, angle_to_vec},
-    io::{
-        snd::MediaPlayer,
-        tex::{Assets, },
-    },
-    game::{DELTA, world::{Grid, Palette}},
+    io::tex::{Assets, },
+    game::{DELTA, difficulty::Difficulty, world::{Grid, Palette}},
 };
 
-use super::{Object, player::Player};
-
-#[derive(Debug, Clone)]
-pub enum Chaser {
-    NoIntel,
-    LastKnown{
-        pos: Point2,
-        vel: Vector2,
-    },
-    LookAround {
-        dir: Vector2,
-    }
-}
-
-impl Chaser {
-    pub fn chasing(&self) -> bool {
-        match *self {
-            Chaser::LastKnown{..} => true,
-            _ => false,
-        }
-    }
-}
+use super::{Object, player::Player, weapon::{Weapon, FireMode}};
 
-impl Default for Chaser {
-    fn default() -> Self {
-        Chaser::NoIntel
-    }
-}
+mod archetypes;
+pub use self::archetypes::*;
+mod patrol;
+pub use self::patrol::*;
+mod behaviour;
+pub use self::behaviour::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Enemy {
     pub pl: Player,
+    #[serde(serialize_with = "archetype_ser", deserialize_with = "archetype_des")]
+    pub kind: &'static Archetype,
+    #[serde(skip)]
+    pub behaviour: Behaviour,
+    /// Seconds the player has been in sight since being noticed
+    #[serde(skip)]
+    pub spotted: f32,
+    /// How close the enemy is to noticing the player, from 0 to 1
     #[serde(skip)]
-    pub behaviour: Chaser,
+    pub awareness: f32,
+    /// Whether the enemy has found a body and is on the lookout
+    #[serde(skip)]
+    pub wary: bool,
+    /// Seconds until a melee strike that's been wound up lands
+    #[serde(skip)]
+    pub windup: Option<f32>,
+    /// Seconds until the enemy may think about throwing a grenade again
+    #[serde(skip)]
+    pub grenade_cooldown: f32,
+    /// Waypoints to walk through in reverse order
+    #[serde(skip)]
+    pub path: Vec<Point2>,
+    /// Stored in its own section of level files
+    #[serde(skip)]
+    pub patrol: Patrol,
 }
 
-pub const VISIBILITY: f32 = ::std::f32::consts::FRAC_PI_4;
+/// How far the chased position can move before the path to it is recalculated
+const REPATH_DISTANCE: f32 = 32.;
+/// How well the player is seen in the peripheral vision compared to straight ahead
+const PERIPHERAL_VISIBILITY: f32 = 0.35;
+/// Awareness lost per second when the player is out of sight
+const AWARENESS_DECAY: f32 = 0.2;
+/// Awareness at which the enemy starts looking towards the player
+const SUSPICIOUS_AWARENESS: f32 = 0.3;
+/// Factor on how fast a wary enemy notices the player
+const WARY_DETECTION: f32 = 2.;
+/// How close an enemy fighting in melee gets to the player
+const MELEE_DISTANCE: f32 = 28.;
+/// How much better than the best weapon an enemy has another has to be for it to want it
+const BETTER_WEAPON: f32 = 1.25;
 
 impl Enemy {
-    pub fn new(obj: Object) -> Enemy {
+    pub fn new(obj: Object, kind: &'static Archetype) -> Enemy {
         Enemy {
-            pl: Player::new(obj),
-            behaviour: Chaser::NoIntel,
+            pl: Player::new(obj).with_health(kind.health),
+            kind,
+            behaviour: Behaviour::Idle,
+            spotted: 0.,
+            awareness: 0.,
+            wary: false,
+            windup: None,
+            grenade_cooldown: 0.,
+            path: Vec::new(),
+            patrol: Patrol::default(),
         }
     }
-    pub fn draw_visibility_cone(&self, ctx: &mut Context, length: f32) -> GameResult<()> {
+    pub fn draw_visibility_cone(&self, ctx: &mut Context) -> GameResult<()> {
         let Object{pos, rot} = self.pl.obj;
-        let dir1 = angle_to_vec(rot - VISIBILITY);
-        let dir2 = angle_to_vec(rot + VISIBILITY);
+        let length = self.kind.view_distance;
+        let dir1 = angle_to_vec(rot - self.kind.view_angle);
+        let dir2 = angle_to_vec(rot + self.kind.view_angle);
+        let per1 = angle_to_vec(rot - self.kind.peripheral_angle);
+        let per2 = angle_to_vec(rot + self.kind.peripheral_angle);
 
-        let mesh = Mesh::new_polyline(ctx, DrawMode::stroke(1.5), &[pos + (length * dir1), pos, pos + (length * dir2)], BLUE)?;
+        let mesh = MeshBuilder::new()
+            .polyline(DrawMode::stroke(1.), &[pos + (length * per1), pos, pos + (length * per2)], Color{a: 0.4, .. BLUE})?
+            .polyline(DrawMode::stroke(1.5), &[pos + (length * dir1), pos, pos + (length * dir2)], BLUE)?
+            .build(ctx)?;
 
         graphics::draw(ctx, &mesh, DrawParam::default())
     }
-    #[inline]
     pub fn draw(&self, ctx: &mut Context, a: &Assets, color: Color) -> GameResult<()> {
-        self.pl.draw(ctx, a, "common/enemy", color)
+        if let Some(time) = self.windup {
+            let weapon = self.pl.wep.melee.weapon;
+            // Go redder the closer the strike is to landing so the player can see it coming
+            let left = if let FireMode::Melee{windup, ..} = weapon.fire_mode { time / windup } else { 0. };
+            self.pl.draw(ctx, a, self.kind.sprite, Color{g: color.g * left, b: color.b * left, .. color})?;
+
+            let Object{pos, rot} = self.pl.obj;
+            let img = a.get_img(ctx, weapon.entity_sprite);
+            Object::with_rot(pos + 20. * angle_to_vec(rot), rot).draw(ctx, &*img, color)
+        } else {
+            self.pl.draw(ctx, a, self.kind.sprite, color)
+        }
     }
-    fn look_towards(&mut self, dist: Vector2) -> bool{
+    fn look_towards(&mut self, dist: Vector2, difficulty: &Difficulty) -> bool{
         let dir = angle_to_vec(self.pl.obj.rot);
 
         let rotation = dir.angle(&dist);
 
-        const ROTATION: f32 = 6. * DELTA;
+        let max_rotation = difficulty.turn_speed * self.kind.turn_speed * DELTA;
 
-        if rotation > ROTATION {
+        if rotation > max_rotation {
             if dir.perp(&dist) > 0. {
-                self.pl.obj.rot += ROTATION;
+                self.pl.obj.rot += max_rotation;
             } else {
-                self.pl.obj.rot -= ROTATION;
+                self.pl.obj.rot -= max_rotation;
             }
             false
         } else {
@@ -91,44 +128,91 @@ impl Enemy {
             true
         }
     }
-    pub fn update(&mut self, ctx: &mut Context, mplayer: &mut MediaPlayer) -> GameResult<()> {
-        if let Some(wep) = self.pl.wep.get_active_mut() {
-            wep.update(ctx, mplayer)?;
-            if wep.cur_clip == 0 && wep.loading_time == 0. {
-                wep.reload(ctx, mplayer)?;
+    /// Follows a path towards `target`, returns whether there's nowhere further to go
+    fn walk_to(&mut self, target: Point2, speed: f32, palette: &Palette, grid: &Grid, difficulty: &Difficulty) -> bool {
+        if self.path.first().map(|&end| (end - target).norm() > REPATH_DISTANCE).unwrap_or(true) {
+            if let Some(mut path) = grid.find_path(palette, self.pl.obj.pos, target) {
+                path.reverse();
+                self.path = path;
+            } else {
+                self.path.clear();
             }
         }
-        match self.behaviour {
-            Chaser::NoIntel => (),
-            Chaser::LastKnown{
-                pos: player_pos,
-                vel
-            } => {
-                let dist = player_pos-self.pl.obj.pos;
-                self.look_towards(dist);
-
-                let distance = dist.norm();
-                const CHASE_SPEED: f32 = 100. * DELTA;
-
-                if distance >= CHASE_SPEED {
-                    let displace = CHASE_SPEED * dist / distance;
-                    self.pl.obj.pos += displace;
-                } else {
-                    self.behaviour = Chaser::LookAround{dir: vel};
-                }
-            }
-            Chaser::LookAround{dir} => {
-                if self.look_towards(dir) {
-                    self.behaviour = Chaser::NoIntel;
-                }
+
+        if let Some(&waypoint) = self.path.last() {
+            let dist = waypoint - self.pl.obj.pos;
+            self.look_towards(dist, difficulty);
+
+            if dist.norm() >= speed * DELTA {
+                self.pl.obj.move_on_grid(dist.normalize(), speed, palette, grid);
+            } else {
+                self.pl.obj.pos = waypoint;
+                self.path.pop();
             }
         }
-        Ok(())
+        self.path.is_empty()
     }
-    pub fn can_see(&self, p: Point2, palette: &Palette, grid: &Grid) -> bool {
+    /// Whether the enemy would pick up a dropped `weapon`,
+    /// either for being better than what it's got or for having ammo for a weapon it's about to run out of
+    pub fn wants(&self, weapon: &Weapon) -> bool {
+        let wep = &self.pl.wep;
+        if weapon.fire_mode.is_melee() {
+            return weapon.power() > BETTER_WEAPON * wep.melee.weapon.power();
+        }
+        let running_out = wep.find(weapon).map(|wep| wep.ammo == 0).unwrap_or(false);
+        running_out || (wep.find(weapon).is_none() && weapon.power() > BETTER_WEAPON * wep.best_power())
+    }
+    /// How well the enemy can see `p`, 1 straight ahead and less in its peripheral vision,
+    /// or `None` if it can't see it at all
+    pub fn sight(&self, p: Point2, palette: &Palette, grid: &Grid) -> Option<f32> {
         let dist = p-self.pl.obj.pos;
         let dir = angle_to_vec(self.pl.obj.rot);
+        let angle = dir.angle(&dist);
 
-        dir.angle(&dist) <= VISIBILITY && grid.ray_cast(palette, self.pl.obj.pos, dist, true).full()
+        if dist.norm() > self.kind.view_distance
+            || angle > self.kind.peripheral_angle.max(self.kind.view_angle)
+            || !grid.ray_cast(palette, self.pl.obj.pos, dist, true).full() {
+            return None;
+        }
+        if angle <= self.kind.view_angle {
+            Some(1.)
+        } else {
+            Some(PERIPHERAL_VISIBILITY)
+        }
+    }
+    /// Fills up the detection meter from seeing the player at `p` with the given `visibility`,
+    /// `conspicuity` being how much they stand out. Returns whether the player has been noticed
+    /// Whether `p` is within the enemy's central view rather than off to the side
+    pub fn faces(&self, p: Point2) -> bool {
+        angle_to_vec(self.pl.obj.rot).angle(&(p - self.pl.obj.pos)) <= self.kind.view_angle
+    }
+    pub fn detect(&mut self, p: Point2, visibility: f32, conspicuity: f32) -> bool {
+        if self.behaviour.is_alert() {
+            self.awareness = 1.;
+            return true;
+        }
+        // Half again as fast right up close and half as fast at the edge of the view distance
+        let closeness = 1. - (p - self.pl.obj.pos).norm() / self.kind.view_distance;
+        let mut rate = visibility * conspicuity * (0.5 + closeness) / self.kind.detection_time;
+        if self.wary {
+            rate *= WARY_DETECTION;
+        }
+
+        self.awareness = (self.awareness + rate * DELTA).min(1.);
+        if self.awareness >= 1. {
+            true
+        } else {
+            if self.awareness >= SUSPICIOUS_AWARENESS {
+                self.suspect(p);
+            }
+            false
+        }
+    }
+    /// Lets the detection meter go down while the player is out of sight
+    pub fn lose_sight(&mut self) {
+        self.spotted = 0.;
+        if !self.behaviour.is_alert() {
+            self.awareness = (self.awareness - AWARENESS_DECAY * DELTA).max(0.);
+        }
     }
 }
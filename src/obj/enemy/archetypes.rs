@@ -0,0 +1,134 @@
+use crate::{
+    util::{sstr, Sstr, DEG2RAD},
+    obj::{health::Health, weapon::{Weapon, WEAPONS}},
+};
+
+use lazy_static::lazy_static;
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de::Error};
+
+use std::fs::File;
+use std::io::Read;
+use std::collections::HashMap;
+
+/// The type enemies get when nothing else is specified
+pub const DEFAULT_ARCHETYPE: &str = "guard";
+
+lazy_static!{
+    pub static ref ARCHETYPES: HashMap<&'static str, Archetype> = {
+        let mut file = File::open("resources/enemies.toml").expect("enemies.toml file");
+        let mut file_contents = String::new();
+        file.read_to_string(&mut file_contents).expect("Reading to succeed");
+
+        let templates: HashMap<Box<str>, ArchetypeTemplate> = toml::from_str(&file_contents).expect("well-defined enemies");
+        templates.into_iter().map(|(k, v)| {
+            let k = sstr(k);
+            (k, v.build(k))
+        }).collect()
+    };
+}
+
+/// A type of enemy
+#[derive(Debug)]
+pub struct Archetype {
+    pub id: Sstr,
+    pub name: Sstr,
+    pub sprite: Sstr,
+    /// Health the enemy starts with
+    pub health: Health,
+    /// Angle to each side of where the enemy is looking that it can see
+    pub view_angle: f32,
+    /// Angle to each side where the enemy can only notice the player slowly
+    pub peripheral_angle: f32,
+    pub view_distance: f32,
+    /// Seconds to notice a player standing still halfway into the view distance
+    pub detection_time: f32,
+    /// Speed when chasing
+    pub move_speed: f32,
+    /// Radians the enemy can turn per second
+    pub turn_speed: f32,
+    /// Time from seeing the player until shooting
+    pub reaction_time: f32,
+    /// Weapon the editor places with a new enemy
+    pub preferred_weapon: Option<&'static Weapon>,
+    /// Grenades the editor gives a new enemy
+    pub grenades: u8,
+}
+
+impl Archetype {
+    #[inline]
+    pub fn default_archetype() -> &'static Self {
+        &ARCHETYPES[DEFAULT_ARCHETYPE]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchetypeTemplate {
+    #[serde(deserialize_with = "crate::util::deserialize_sstr")]
+    name: Sstr,
+    #[serde(deserialize_with = "crate::util::deserialize_sstr")]
+    sprite: Sstr,
+    hp: f32,
+    armour: f32,
+    view_angle: f32,
+    peripheral_angle: f32,
+    view_distance: f32,
+    detection_time: f32,
+    move_speed: f32,
+    turn_speed: f32,
+    reaction_time: f32,
+    #[serde(default)]
+    preferred_weapon: Option<String>,
+    #[serde(default)]
+    grenades: u8,
+}
+
+impl ArchetypeTemplate {
+    fn build(self, id: &'static str) -> Archetype {
+        let ArchetypeTemplate {
+            name,
+            sprite,
+            hp,
+            armour,
+            view_angle,
+            peripheral_angle,
+            view_distance,
+            detection_time,
+            move_speed,
+            turn_speed,
+            reaction_time,
+            preferred_weapon,
+            grenades,
+        } = self;
+
+        Archetype {
+            id,
+            name,
+            sprite,
+            health: Health {
+                hp,
+                armour,
+            },
+            view_angle: view_angle * DEG2RAD,
+            peripheral_angle: peripheral_angle * DEG2RAD,
+            view_distance,
+            detection_time,
+            move_speed,
+            turn_speed: turn_speed * DEG2RAD,
+            reaction_time,
+            preferred_weapon: preferred_weapon.map(|id| WEAPONS.get(&*id).expect("known preferred weapon")),
+            grenades,
+        }
+    }
+}
+
+/// Serialize an archetype as its id
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn archetype_ser<S: Serializer>(a: &&'static Archetype, ser: S) -> Result<S::Ok, S::Error> {
+    a.id.serialize(ser)
+}
+
+/// Deserialize an archetype from its id
+pub fn archetype_des<'de, D: Deserializer<'de>>(des: D) -> Result<&'static Archetype, D::Error> {
+    let id = <Box<str>>::deserialize(des)?;
+    ARCHETYPES.get(&*id).ok_or_else(|| D::Error::custom(format!("no enemy type called `{}'", id)))
+}
@@ -0,0 +1,506 @@
+use std::fmt::{self, Display};
+use std::mem;
+use std::f32::consts::{PI, FRAC_PI_4};
+
+use crate::{
+    util::{Vector2, Point2, angle_from_vec, angle_to_vec},
+    game::{DELTA, difficulty::Difficulty, world::{Grid, Palette, GameEvent, Cover}},
+    obj::alarm::ALARM_REACH,
+};
+
+use super::{Enemy, REPATH_DISTANCE, MELEE_DISTANCE};
+
+/// Seconds spent looking towards something faint before shrugging it off
+const SUSPICION_TIME: f32 = 2.;
+/// Seconds spent searching before giving up
+const SEARCH_TIME: f32 = 10.;
+/// How far from where the player was lost to look for them
+const SEARCH_RADIUS: f32 = 192.;
+/// How far to get away from the player before reloading is done
+const RETREAT_DISTANCE: f32 = 160.;
+/// How far to get away from the player when fleeing
+const FLEE_DISTANCE: f32 = 640.;
+/// Fraction of the starting hp under which enemies flee
+const FLEE_HEALTH: f32 = 0.25;
+/// How strong a noise has to be to be investigated right away instead of just being suspicious of it
+const CLEAR_NOISE: f32 = 0.5;
+/// Fraction of the chasing speed used when investigating or searching
+const INVESTIGATE_SPEED: f32 = 0.7;
+/// Fraction of the chasing speed used when patrolling
+const PATROL_SPEED: f32 = 0.5;
+/// How far away to look for cover
+const COVER_RADIUS: f32 = 192.;
+/// Seconds spent hiding in cover before peeking out
+const COVER_TIME: f32 = 1.5;
+/// Seconds spent peeking out of cover to shoot
+const PEEK_TIME: f32 = 2.;
+
+/// What an enemy is doing
+#[derive(Debug, Clone)]
+pub enum Behaviour {
+    /// Standing still with nothing to do
+    Idle,
+    /// Walking its patrol route
+    Patrol,
+    /// Looking towards something faint for a while
+    Suspicious {
+        pos: Point2,
+        time: f32,
+    },
+    /// Going to see what made a noise
+    Investigate {
+        pos: Point2,
+    },
+    /// Fighting the player, going to where they were last seen
+    Combat {
+        pos: Point2,
+        vel: Vector2,
+    },
+    /// Going around to `via` to come at the player from another side, then fighting
+    Flank {
+        via: Point2,
+        pos: Point2,
+    },
+    /// Running to the alarm at `alarm` to alert everyone
+    RaiseAlarm {
+        alarm: Point2,
+        pos: Point2,
+    },
+    /// Looking around the area the player was lost in
+    Search {
+        /// Places left to look in reverse order
+        points: Vec<Point2>,
+        time: f32,
+    },
+    /// Getting to cover from the player at `from`, or just away from them if there is none, to reload
+    Reload {
+        from: Point2,
+        to: Option<Point2>,
+        /// Where to peek out from when done if `to` is cover
+        peek: Option<Point2>,
+    },
+    /// Hiding from the player at `from` after being shot, finding cover first if `cover` is `None`
+    TakeCover {
+        from: Point2,
+        cover: Option<Cover>,
+        time: f32,
+    },
+    /// Stepping out of cover to shoot at the player
+    Peek {
+        from: Point2,
+        cover: Cover,
+        time: f32,
+    },
+    /// Going at the player with a melee weapon for lack of anything better
+    Melee {
+        pos: Point2,
+    },
+    /// Going to pick up a weapon, ammo or health at `pos`, having last seen the player at `from`
+    Scavenge {
+        pos: Point2,
+        from: Point2,
+    },
+    /// Running away from the player for good
+    Flee {
+        from: Point2,
+        to: Option<Point2>,
+    },
+}
+
+impl Behaviour {
+    /// Whether the enemy knows about the player
+    pub fn is_alert(&self) -> bool {
+        match *self {
+            Behaviour::Combat{..} | Behaviour::Flank{..} | Behaviour::RaiseAlarm{..}
+            | Behaviour::Reload{..} | Behaviour::TakeCover{..} | Behaviour::Peek{..} | Behaviour::Melee{..} | Behaviour::Scavenge{..}
+            | Behaviour::Flee{..} => true,
+            _ => false,
+        }
+    }
+    /// Whether the enemy should shoot at the player when seeing them
+    #[inline]
+    pub fn is_fighting(&self) -> bool {
+        match *self {
+            Behaviour::Combat{..} | Behaviour::Peek{..} => true,
+            _ => false,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        use self::Behaviour::*;
+        match *self {
+            Idle => "idle",
+            Patrol => "patrol",
+            Suspicious{..} => "suspicious",
+            Investigate{..} => "investigate",
+            Combat{..} => "combat",
+            Flank{..} => "flank",
+            RaiseAlarm{..} => "raise alarm",
+            Search{..} => "search",
+            Reload{..} => "reload",
+            TakeCover{..} => "take cover",
+            Peek{..} => "peek",
+            Melee{..} => "melee",
+            Scavenge{..} => "scavenge",
+            Flee{..} => "flee",
+        }
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Behaviour::Idle
+    }
+}
+
+impl Display for Behaviour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Behaviour::*;
+        match self {
+            Idle | Patrol => self.name().fmt(f),
+            Suspicious{pos, time} => write!(f, "suspicious of ({:.0}, {:.0}) for {:.1}s", pos.x, pos.y, time),
+            Investigate{pos} => write!(f, "investigating ({:.0}, {:.0})", pos.x, pos.y),
+            Combat{pos, ..} => write!(f, "in combat, player last seen at ({:.0}, {:.0})", pos.x, pos.y),
+            Flank{via, ..} => write!(f, "flanking via ({:.0}, {:.0})", via.x, via.y),
+            RaiseAlarm{alarm, ..} => write!(f, "raising the alarm at ({:.0}, {:.0})", alarm.x, alarm.y),
+            Search{points, time} => write!(f, "searching {} more places for {:.1}s", points.len(), time),
+            Reload{from, ..} => write!(f, "retreating from ({:.0}, {:.0}) to reload", from.x, from.y),
+            TakeCover{from, ..} => write!(f, "taking cover from ({:.0}, {:.0})", from.x, from.y),
+            Peek{from, time, ..} => write!(f, "peeking out at ({:.0}, {:.0}) for {:.1}s", from.x, from.y, time),
+            Melee{pos} => write!(f, "going at ({:.0}, {:.0}) in melee", pos.x, pos.y),
+            Scavenge{pos, ..} => write!(f, "picking something up at ({:.0}, {:.0})", pos.x, pos.y),
+            Flee{from, ..} => write!(f, "fleeing from ({:.0}, {:.0})", from.x, from.y),
+        }
+    }
+}
+
+impl Enemy {
+    /// Acts on the current behaviour, turning as fast as the `difficulty` lets enemies turn
+    pub fn update(&mut self, events: &mut Vec<GameEvent>, palette: &Palette, grid: &Grid, difficulty: &Difficulty) {
+        if self.pl.wep.get_active().map(|wep| wep.is_empty()).unwrap_or(true) {
+            // Fall back to any other weapon that still has some ammo
+            self.pl.wep.active = self.pl.wep.best_slot();
+        }
+        self.pl.wep.melee.update(events);
+        let mut reloading = false;
+        let mut out_of_ammo = self.pl.wep.get_active().is_none();
+        if let Some(wep) = self.pl.wep.get_active_mut() {
+            wep.update(events);
+            if wep.cur_clip == 0 && wep.loading_time == 0. {
+                if wep.ammo == 0 {
+                    out_of_ammo = true;
+                } else {
+                    wep.reload(events);
+                    reloading = true;
+                }
+            }
+        }
+
+        use self::Behaviour::*;
+        let behaviour = mem::replace(&mut self.behaviour, Idle);
+        self.behaviour = match behaviour {
+            Idle if !self.patrol.route.is_empty() => Patrol,
+            Idle => Idle,
+            Patrol => {
+                self.follow_patrol(palette, grid, difficulty);
+                Patrol
+            }
+            Suspicious{pos, time} => {
+                self.look_towards(pos - self.pl.obj.pos, difficulty);
+                if time > 0. {
+                    Suspicious{pos, time: time - DELTA}
+                } else {
+                    self.calm()
+                }
+            }
+            Investigate{pos} => {
+                if self.walk_to(pos, INVESTIGATE_SPEED * self.kind.move_speed, palette, grid, difficulty) {
+                    let dir = angle_to_vec(self.pl.obj.rot);
+                    Search{points: self.search_points(pos, dir, palette, grid), time: SEARCH_TIME}
+                } else {
+                    Investigate{pos}
+                }
+            }
+            Combat{pos, vel: _} if out_of_ammo => Melee{pos},
+            Combat{pos, vel: _} if reloading => Reload{from: pos, to: None, peek: None},
+            Combat{pos, vel} => {
+                if self.walk_to(pos, self.kind.move_speed, palette, grid, difficulty) {
+                    Search{points: self.search_points(pos, vel, palette, grid), time: SEARCH_TIME}
+                } else {
+                    Combat{pos, vel}
+                }
+            }
+            Flank{pos, ..} if out_of_ammo => Melee{pos},
+            Flank{via, pos} => {
+                if self.walk_to(via, self.kind.move_speed, palette, grid, difficulty) {
+                    Combat{pos, vel: Vector2::new(0., 0.)}
+                } else {
+                    Flank{via, pos}
+                }
+            }
+            RaiseAlarm{alarm, pos} => {
+                // Raising it is up to the world, but an alarm that can't be reached is given up on
+                if self.walk_to(alarm, self.kind.move_speed, palette, grid, difficulty) && (alarm - self.pl.obj.pos).norm() > ALARM_REACH {
+                    Combat{pos, vel: Vector2::new(0., 0.)}
+                } else {
+                    RaiseAlarm{alarm, pos}
+                }
+            }
+            Search{mut points, time} => {
+                if let Some(&p) = points.last() {
+                    if self.walk_to(p, INVESTIGATE_SPEED * self.kind.move_speed, palette, grid, difficulty) {
+                        points.pop();
+                    }
+                }
+                if points.is_empty() || time <= 0. {
+                    self.calm()
+                } else {
+                    Search{points, time: time - DELTA}
+                }
+            }
+            Reload{from, to, peek} => {
+                let (to, peek) = match to {
+                    Some(to) => (to, peek),
+                    None => match grid.find_cover(palette, self.pl.obj.pos, from, COVER_RADIUS) {
+                        Some(cover) => (cover.pos, Some(cover.peek)),
+                        None => (self.away_from(from, RETREAT_DISTANCE, palette, grid), None),
+                    },
+                };
+                self.walk_to(to, self.kind.move_speed, palette, grid, difficulty);
+
+                if self.pl.wep.get_active().map(|wep| wep.loading_time <= 0.).unwrap_or(true) {
+                    match peek {
+                        Some(peek) => Peek{from, cover: Cover{pos: to, peek}, time: PEEK_TIME},
+                        None => Combat{pos: from, vel: Vector2::new(0., 0.)},
+                    }
+                } else {
+                    Reload{from, to: Some(to), peek}
+                }
+            }
+            TakeCover{from, ..} if out_of_ammo => Melee{pos: from},
+            TakeCover{from, cover, time} => {
+                match cover.or_else(|| grid.find_cover(palette, self.pl.obj.pos, from, COVER_RADIUS)) {
+                    Some(cover) => if self.walk_to(cover.pos, self.kind.move_speed, palette, grid, difficulty) {
+                        self.look_towards(from - self.pl.obj.pos, difficulty);
+                        if time <= 0. {
+                            Peek{from, cover, time: PEEK_TIME}
+                        } else {
+                            TakeCover{from, cover: Some(cover), time: time - DELTA}
+                        }
+                    } else {
+                        TakeCover{from, cover: Some(cover), time}
+                    },
+                    // Nowhere to hide so just keep fighting
+                    None => Combat{pos: from, vel: Vector2::new(0., 0.)},
+                }
+            }
+            Peek{from, ..} if out_of_ammo => Melee{pos: from},
+            Peek{from, cover, ..} if reloading => Reload{from, to: Some(cover.pos), peek: Some(cover.peek)},
+            Peek{from, cover, time} => {
+                if self.walk_to(cover.peek, self.kind.move_speed, palette, grid, difficulty) {
+                    self.look_towards(from - self.pl.obj.pos, difficulty);
+                    if time > 0. {
+                        Peek{from, cover, time: time - DELTA}
+                    } else if self.spotted > 0. {
+                        // Still being shot at so get back into cover
+                        TakeCover{from, cover: Some(cover), time: COVER_TIME}
+                    } else {
+                        // The player's gone somewhere else so go after them
+                        Combat{pos: from, vel: Vector2::new(0., 0.)}
+                    }
+                } else {
+                    Peek{from, cover, time}
+                }
+            }
+            Melee{pos} if !out_of_ammo => Combat{pos, vel: Vector2::new(0., 0.)},
+            // Committed to the strike so it can be dodged
+            Melee{pos} if self.windup.is_some() => Melee{pos},
+            Melee{pos} => {
+                let dist = pos - self.pl.obj.pos;
+                if dist.norm() > MELEE_DISTANCE {
+                    if self.walk_to(pos, self.kind.move_speed, palette, grid, difficulty) {
+                        Search{points: self.search_points(pos, dist, palette, grid), time: SEARCH_TIME}
+                    } else {
+                        Melee{pos}
+                    }
+                } else if self.spotted > 0. {
+                    self.look_towards(dist, difficulty);
+                    Melee{pos}
+                } else {
+                    Search{points: self.search_points(pos, dist, palette, grid), time: SEARCH_TIME}
+                }
+            }
+            Scavenge{pos, from} => {
+                if self.walk_to(pos, self.kind.move_speed, palette, grid, difficulty) {
+                    Combat{pos: from, vel: Vector2::new(0., 0.)}
+                } else {
+                    Scavenge{pos, from}
+                }
+            }
+            Flee{from, to} => {
+                let to = to.unwrap_or_else(|| self.away_from(from, FLEE_DISTANCE, palette, grid));
+                if self.walk_to(to, self.kind.move_speed, palette, grid, difficulty) {
+                    if (self.pl.obj.pos - from).norm() >= FLEE_DISTANCE {
+                        Idle
+                    } else {
+                        // Not far enough away yet so keep going
+                        Flee{from, to: None}
+                    }
+                } else {
+                    Flee{from, to: Some(to)}
+                }
+            }
+        };
+    }
+    /// Makes the enemy notice the player at `pos` moving with `vel`
+    pub fn see(&mut self, pos: Point2, vel: Vector2) {
+        match self.behaviour {
+            Behaviour::Reload{ref mut from, ..} | Behaviour::TakeCover{ref mut from, ..} | Behaviour::Peek{ref mut from, ..}
+            | Behaviour::Scavenge{ref mut from, ..} | Behaviour::Flee{ref mut from, ..} => *from = pos,
+            Behaviour::RaiseAlarm{pos: ref mut p, ..} | Behaviour::Melee{pos: ref mut p} => *p = pos,
+            _ => self.behaviour = Behaviour::Combat{pos, vel},
+        }
+    }
+    /// Makes the enemy learn from an ally that the player is at `pos`,
+    /// going around through `via` if given instead of straight at them
+    pub fn inform(&mut self, pos: Point2, via: Option<Point2>) {
+        use self::Behaviour::*;
+        match self.behaviour {
+            Combat{pos: ref mut p, ..} | Flank{pos: ref mut p, ..} | RaiseAlarm{pos: ref mut p, ..}
+            | Melee{pos: ref mut p} => *p = pos,
+            Reload{ref mut from, ..} | TakeCover{ref mut from, ..} | Peek{ref mut from, ..}
+            | Scavenge{ref mut from, ..} | Flee{ref mut from, ..} => *from = pos,
+            _ => self.behaviour = match via {
+                Some(via) => Flank{via, pos},
+                None => Combat{pos, vel: Vector2::new(0., 0.)},
+            },
+        }
+    }
+    /// Sends the enemy to raise the alarm at `alarm`, the player having been at `pos`
+    #[inline]
+    pub fn raise_alarm(&mut self, alarm: Point2, pos: Point2) {
+        self.behaviour = Behaviour::RaiseAlarm{alarm, pos};
+    }
+    /// Makes the enemy react to an alarm being raised while the player is at `pos`
+    pub fn hear_alarm(&mut self, pos: Point2) {
+        match self.behaviour {
+            Behaviour::RaiseAlarm{..} => self.behaviour = Behaviour::Combat{pos, vel: Vector2::new(0., 0.)},
+            _ => self.inform(pos, None),
+        }
+    }
+    /// Makes the enemy react to a noise at `pos` heard with a `strength` between 0 and 1
+    pub fn hear(&mut self, pos: Point2, strength: f32) {
+        use self::Behaviour::*;
+        self.behaviour = match self.behaviour {
+            Idle | Patrol if strength < CLEAR_NOISE => Suspicious{pos, time: SUSPICION_TIME},
+            Idle | Patrol | Suspicious{..} | Investigate{..} | Search{..} => Investigate{pos},
+            Combat{..} | Flank{..} | RaiseAlarm{..} | Reload{..} | TakeCover{..} | Peek{..} | Melee{..} | Scavenge{..}
+            | Flee{..} => return,
+        };
+    }
+    /// Makes the enemy look towards `pos` where it's starting to notice the player
+    pub fn suspect(&mut self, pos: Point2) {
+        use self::Behaviour::*;
+        match self.behaviour {
+            Idle | Patrol | Suspicious{..} => self.behaviour = Suspicious{pos, time: SUSPICION_TIME},
+            _ => (),
+        }
+    }
+    /// Makes the enemy react to finding a body at `pos`, searching the area around it
+    pub fn discover_body(&mut self, pos: Point2, palette: &Palette, grid: &Grid) {
+        self.wary = true;
+        if !self.behaviour.is_alert() {
+            let dir = pos - self.pl.obj.pos;
+            let mut points = self.search_points(pos, dir, palette, grid);
+            // Go look at the body first
+            points.push(pos);
+            self.behaviour = Behaviour::Search{points, time: SEARCH_TIME};
+        }
+    }
+    /// Makes the enemy react to being hurt by something coming from `source`
+    pub fn hurt(&mut self, source: Point2) {
+        match self.behaviour {
+            Behaviour::Flee{..} => (),
+            _ if self.pl.health.hp < FLEE_HEALTH * self.kind.health.hp => {
+                self.behaviour = Behaviour::Flee{from: source, to: None};
+            }
+            _ if !self.behaviour.is_alert() => {
+                self.behaviour = Behaviour::Combat{pos: source, vel: Vector2::new(0., 0.)};
+            }
+            // Get out of the line of fire, using where the player was last seen as that's where the shots come from
+            Behaviour::Combat{pos, ..} => {
+                self.behaviour = Behaviour::TakeCover{from: pos, cover: None, time: COVER_TIME};
+            }
+            Behaviour::Peek{from, cover, ..} => {
+                self.behaviour = Behaviour::TakeCover{from, cover: Some(cover), time: COVER_TIME};
+            }
+            _ => (),
+        }
+    }
+    /// What to go back to doing when there's nothing to worry about
+    fn calm(&self) -> Behaviour {
+        if self.patrol.route.is_empty() {
+            Behaviour::Idle
+        } else {
+            Behaviour::Patrol
+        }
+    }
+    fn follow_patrol(&mut self, palette: &Palette, grid: &Grid, difficulty: &Difficulty) {
+        let waypoint = match self.patrol.route.get(self.patrol.next) {
+            Some(waypoint) => waypoint.clone(),
+            None => return,
+        };
+
+        // Having been somewhere else since arriving means the waypoint has to be walked to again
+        if self.patrol.waiting.is_some() && (waypoint.pos - self.pl.obj.pos).norm() > REPATH_DISTANCE {
+            self.patrol.waiting = None;
+        }
+
+        match self.patrol.waiting {
+            None => if self.walk_to(waypoint.pos, PATROL_SPEED * self.kind.move_speed, palette, grid, difficulty) {
+                self.patrol.waiting = Some(waypoint.wait);
+            }
+            Some(time) => {
+                let facing = waypoint.facing.map(|rot| self.look_towards(angle_to_vec(rot), difficulty)).unwrap_or(true);
+                if time <= 0. && facing {
+                    self.patrol.advance();
+                } else {
+                    self.patrol.waiting = Some(time - DELTA);
+                }
+            }
+        }
+    }
+    /// Places around `around` to look in, starting with those in the direction `dir`
+    fn search_points(&self, around: Point2, dir: Vector2, palette: &Palette, grid: &Grid) -> Vec<Point2> {
+        let start = if dir.norm_squared() > 0. { angle_from_vec(dir) } else { self.pl.obj.rot };
+        let mut points: Vec<_> = [0., 2., -2., 1., -1., 3., -3., 4.].iter()
+            .map(|&n| angle_to_vec(start + n * FRAC_PI_4))
+            .filter_map(|dir| {
+                let hit = grid.ray_cast(palette, around, SEARCH_RADIUS * dir, true).into_point();
+                // Stay a bit away from walls
+                let dist = (hit - around).norm() - 16.;
+                if dist >= 48. {
+                    Some(around + dist * dir)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        points.reverse();
+        points
+    }
+    /// A place about `distance` away in the direction away from `from`
+    fn away_from(&self, from: Point2, distance: f32, palette: &Palette, grid: &Grid) -> Point2 {
+        let pos = self.pl.obj.pos;
+        let start = if pos != from { angle_from_vec(pos - from) } else { self.pl.obj.rot + PI };
+
+        [0., 1., -1., 2., -2., 3., -3.].iter()
+            .map(|&n| angle_to_vec(start + n * FRAC_PI_4))
+            .map(|dir| {
+                let hit = grid.ray_cast(palette, pos, distance * dir, true).into_point();
+                let dist = ((hit - pos).norm() - 16.).max(0.);
+                pos + dist * dir
+            })
+            .max_by(|a, b| (a - from).norm().partial_cmp(&(b - from).norm()).unwrap())
+            .unwrap_or(pos)
+    }
+}
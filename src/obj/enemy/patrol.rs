@@ -0,0 +1,49 @@
+use crate::util::Point2;
+
+/// A point on a patrol route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    #[serde(with = "crate::io::save::Point2Def")]
+    pub pos: Point2,
+    /// Seconds to stay at the point before walking on
+    pub wait: f32,
+    /// Direction to look in while staying at the point
+    pub facing: Option<f32>,
+}
+
+impl Waypoint {
+    #[inline]
+    pub fn new(pos: Point2) -> Self {
+        Waypoint {
+            pos,
+            wait: 0.,
+            facing: None,
+        }
+    }
+}
+
+/// Waypoints an enemy walks through in order while it hasn't noticed anything
+#[derive(Debug, Default, Clone)]
+pub struct Patrol {
+    pub route: Vec<Waypoint>,
+    /// Index of the waypoint being walked to or stayed at
+    pub next: usize,
+    /// Seconds left to stay at the next waypoint if it has been reached
+    pub waiting: Option<f32>,
+}
+
+impl Patrol {
+    #[inline]
+    pub fn new(route: Vec<Waypoint>) -> Self {
+        Patrol {
+            route,
+            next: 0,
+            waiting: None,
+        }
+    }
+    /// Moves on to the waypoint after the next one, going back to the first one after the last
+    pub fn advance(&mut self) {
+        self.waiting = None;
+        self.next = (self.next + 1) % self.route.len().max(1);
+    }
+}
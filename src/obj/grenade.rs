@@ -2,23 +2,35 @@ use ggez::{Context, GameResult, graphics::{self, WHITE,WrapMode, Mesh, DrawParam
 use std::{iter, f32::consts::PI};
 
 use crate::{
-    util::{angle_to_vec, Vector2},
+    util::{angle_to_vec, angle_from_vec, Vector2, Point2},
     game::{
         DELTA,
-        world::{Grid, Palette},
-    },
-    io::{
-        snd::MediaPlayer,
-        tex::{Assets, },
+        world::{Grid, Palette, GameEvent},
     },
+    io::tex::{Assets, },
 };
-use super::{Object, player::Player, enemy::Enemy, health::Health};
+use super::{Object, player::Player, enemy::Enemy};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Utilities {
     pub grenades: u8,
 }
 
+/// Grenades dropped by someone to be picked up
+#[derive(Debug, Clone, Copy)]
+pub struct GrenadeDrop {
+    pub pos: Point2,
+    pub grenades: u8,
+}
+
+impl GrenadeDrop {
+    #[inline]
+    pub fn draw(&self, ctx: &mut Context, a: &Assets) -> GameResult<()> {
+        let img = a.get_img(ctx, "weapons/pineapple");
+        Object::new(self.pos).draw(ctx, &*img, WHITE)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Grenade {
     pub obj: Object,
@@ -33,7 +45,8 @@ pub enum GrenadeState {
     },
     Explosion {
         alive_time: f32,
-        mesh: Mesh,
+        /// Points the explosion reaches in each direction
+        outline: Box<[Point2]>,
     }
 
 }
@@ -41,13 +54,25 @@ pub enum GrenadeState {
 const EXPLOSION_LIFETIME: f32 = 0.5;
 const DEC: f32 = 1.4;
 
-const RANGE: f32 = 144.;
+/// How far away an explosion hurts
+pub const RANGE: f32 = 144.;
 const LETHAL_RANGE: f32 = 64.;
+/// Seconds from a grenade being thrown until it explodes
+const FUSE: f32 = 1.5;
+/// Speed of a grenade thrown as hard as possible
+pub const THROW_SPEED: f32 = 620.;
+/// How far in front of the thrower a grenade is let go
+pub const THROW_DISTANCE: f32 = 20.;
+/// How far from the target a planned throw may end up
+const AIM_TOLERANCE: f32 = 48.;
+
+const NUM_VERTICES: u32 = 120;
+const RADIANS_PER_VERT: f32 = (360. / NUM_VERTICES as f32) * PI/180.;
 
 impl Grenade {
     #[inline]
-    pub fn apply_damage(health: &mut Health, high: bool) {
-        health.weapon_damage(if high { 105.} else {55.}, 0.85);
+    pub fn apply_damage(pl: &mut Player, high: bool) {
+        pl.weapon_damage(if high { 105.} else {55.}, 0.85);
     }
     #[inline]
     pub fn draw(&self, ctx: &mut Context, a: &Assets) -> GameResult<()> {
@@ -56,15 +81,19 @@ impl Grenade {
                 let img = a.get_img(ctx, "weapons/pineapple");
                 self.obj.draw(ctx, &*img, WHITE)
             }
-            GrenadeState::Explosion { mesh, .. } => {
-                graphics::draw(ctx, mesh, DrawParam::default())
+            GrenadeState::Explosion { outline, .. } => {
+                let mesh = self.make_mesh(ctx, a, outline)?;
+                graphics::draw(ctx, &mesh, DrawParam::default())
             }
         }
     }
-    fn make_mesh(&self, ctx: &mut Context, a: &Assets, palette: &Palette, grid: &Grid) -> GameResult<Mesh> {
-        const NUM_VERTICES: u32 = 120;
-        const RADIANS_PER_VERT: f32 = (360. / NUM_VERTICES as f32) * PI/180.;
-
+    fn make_outline(&self, palette: &Palette, grid: &Grid) -> Box<[Point2]> {
+        (0..NUM_VERTICES).map(|i| {
+            let angle = angle_to_vec(i as f32 * RADIANS_PER_VERT);
+            grid.ray_cast(palette, self.obj.pos, angle*RANGE, true).into_point()
+        }).collect()
+    }
+    fn make_mesh(&self, ctx: &mut Context, a: &Assets, outline: &[Point2]) -> GameResult<Mesh> {
         let mut expl_img = (a.get_img(ctx, "weapons/explosion")).clone();
         expl_img.set_wrap(WrapMode::Mirror,WrapMode::Mirror);
         let centre = graphics::Vertex {
@@ -72,11 +101,10 @@ impl Grenade {
             uv: [0.0, 0.0],
             color: [1.0, 1.0, 1.0, 1.0],
         };
-        let vertices: Vec<_> = (0..NUM_VERTICES).map(|i| {
+        let vertices: Vec<_> = outline.iter().enumerate().map(|(i, p)| {
             let angle = angle_to_vec(i as f32 * RADIANS_PER_VERT);
-            let cast = grid.ray_cast(palette, self.obj.pos, angle*RANGE, true);
             graphics::Vertex{
-                pos: cast.into_point().coords.into(),
+                pos: p.coords.into(),
                 uv: angle.into(),
                 color: [1.0, 1.0, 1.0, 1.0],
             }
@@ -100,7 +128,7 @@ impl Grenade {
 
             let d_player = player.obj.pos-start;
             if d_player.norm() < RANGE && grid.ray_cast(palette, start, d_player, true).full() {
-                Self::apply_damage(&mut player.health, d_player.norm() <= LETHAL_RANGE);
+                Self::apply_damage(player, d_player.norm() <= LETHAL_RANGE);
                 player_hit = true;
             } else {
                 player_hit = false;
@@ -109,7 +137,7 @@ impl Grenade {
             for (i, enem) in enemies.iter_mut().enumerate().rev() {
                 let d_enemy = enem.pl.obj.pos - start;
                 if d_enemy.norm() < 144. && grid.ray_cast(palette, start, d_enemy, true).full() {
-                    Self::apply_damage(&mut enem.pl.health, d_enemy.norm() <= 64.);
+                    Self::apply_damage(&mut enem.pl, d_enemy.norm() <= 64.);
                     enemy_hits.push(i);
                 }
             }
@@ -137,17 +165,58 @@ impl Grenade {
                 return GrenadeUpdate::None;
             }
         }
-        let cast = grid.ray_cast(palette, start, d_pos, true);
+        Self::bounce(obj, vel, d_pos, palette, grid);
+        GrenadeUpdate::None
+    }
+    /// Moves by `d_pos` bouncing off any wall in the way
+    fn bounce(obj: &mut Object, vel: &mut Vector2, d_pos: Vector2, palette: &Palette, grid: &Grid) {
+        let cast = grid.ray_cast(palette, obj.pos, d_pos, true);
         obj.pos = cast.into_point();
         if let Some(to_wall) = cast.half_vec() {
             let clip = cast.clip();
             obj.pos += clip -  2. * clip.dot(&to_wall)/to_wall.norm_squared() * to_wall;
             *vel -= 2. * vel.dot(&to_wall)/to_wall.norm_squared() * to_wall;
         }
-        GrenadeUpdate::None
+    }
+    /// Where a grenade thrown from `pos` with `vel` explodes if it doesn't hit anyone on the way
+    pub fn landing_point(pos: Point2, mut vel: Vector2, palette: &Palette, grid: &Grid) -> Point2 {
+        let mut obj = Object::new(pos);
+        let mut fuse = FUSE;
+        while fuse > DELTA {
+            fuse -= DELTA;
+            let d_vel = -DEC * vel * DELTA;
+            let d_pos = 0.5 * DELTA * d_vel + vel * DELTA;
+            vel += d_vel;
+            Self::bounce(&mut obj, &mut vel, d_pos, palette, grid);
+        }
+        obj.pos
+    }
+    /// Where a grenade thrown from `from` towards `rot` is let go
+    #[inline]
+    pub fn throw_point(from: Point2, rot: f32) -> Point2 {
+        from + THROW_DISTANCE * angle_to_vec(rot)
+    }
+    /// Finds an angle and speed for someone standing at `from` to throw a grenade with to have it explode near `target`,
+    /// bouncing off walls if that's what it takes
+    pub fn aim(from: Point2, target: Point2, palette: &Palette, grid: &Grid) -> Option<(f32, f32)> {
+        let dist = target - from;
+        if dist.norm_squared() == 0. {
+            return None;
+        }
+        let direct = angle_from_vec(dist);
+
+        (-8..=8)
+            .flat_map(|n| (4..=10).map(move |s| (direct + n as f32 * PI / 24., s as f32 / 10. * THROW_SPEED)))
+            .map(|(rot, speed)| {
+                let miss = (Self::landing_point(Self::throw_point(from, rot), speed * angle_to_vec(rot), palette, grid) - target).norm();
+                (rot, speed, miss)
+            })
+            .filter(|&(_, _, miss)| miss <= AIM_TOLERANCE)
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+            .map(|(rot, speed, _)| (rot, speed))
     }
 
-    pub fn update(&mut self, ctx: &mut Context, a: &Assets, palette: &Palette, grid: &Grid, player: &mut Player, enemies: &mut [Enemy]) -> GameResult<GrenadeUpdate> {
+    pub fn update(&mut self, palette: &Palette, grid: &Grid, player: &mut Player, enemies: &mut [Enemy]) -> GrenadeUpdate {
         let update = match self.state {
             GrenadeState::Explosion{ref mut alive_time, ..} => {
                 *alive_time += DELTA;
@@ -164,34 +233,39 @@ impl Grenade {
         if let GrenadeUpdate::Explosion{..} = update {
             self.state = GrenadeState::Explosion {
                 alive_time: 0.,
-                mesh: self.make_mesh(ctx, a, palette, grid)?
+                outline: self.make_outline(palette, grid),
             };
         }
-        Ok(update)
+        update
     }
 }
 
 impl Utilities {
-    pub fn throw_grenade(&mut self, ctx: &mut Context, mplayer: &mut MediaPlayer) -> GameResult<Option<GrenadeMaker>> {
+    pub fn throw_grenade(&mut self, events: &mut Vec<GameEvent>) -> Option<GrenadeMaker> {
         if self.grenades > 0 {
             self.grenades -= 1;
 
-            mplayer.play(ctx, "throw")?;
-            Ok(Some(GrenadeMaker(620.)))
+            events.push(GameEvent::Sound("throw"));
+            Some(GrenadeMaker(THROW_SPEED))
         } else {
-            mplayer.play(ctx, "cock")?;
-            Ok(None)
+            events.push(GameEvent::Sound("cock"));
+            None
         }
     }
 }
 
 pub struct GrenadeMaker(f32);
 impl GrenadeMaker {
+    /// Throws the grenade at `speed` instead of as hard as possible
+    #[inline]
+    pub fn with_speed(self, speed: f32) -> Self {
+        GrenadeMaker(speed.min(self.0))
+    }
     pub fn make(self, mut obj: Object) -> Grenade {
         let vel = angle_to_vec(obj.rot) * self.0;
         obj.rot = 0.;
         Grenade {
-            state: GrenadeState::Fused{fuse: 1.5},
+            state: GrenadeState::Fused{fuse: FUSE},
             vel,
             obj,
         }
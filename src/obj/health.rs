@@ -1,6 +1,6 @@
 use crate::ext::FloatExt;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Health {
     pub hp: f32,
     pub armour: f32,
@@ -12,11 +12,15 @@ pub mod bullet;
 pub mod pickup;
 pub mod decal;
 pub mod grenade;
+pub mod door;
+pub mod alarm;
+pub mod corpse;
+pub mod effect;
 
 use crate::game::world::{Grid, Palette};
 use crate::game::DELTA;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 /// A simple object that can be drawn to the screen
 pub struct Object {
     #[serde(with = "crate::io::save::Point2Def")]
@@ -4,13 +4,11 @@ use ggez::{Context, GameResult, graphics::{self, WHITE, Color}};
 
 use crate::{
     util::{Point2, angle_to_vec},
-    io::{
-        snd::MediaPlayer,
-        tex::{Assets, },
-    },
+    io::tex::{Assets, },
+    game::world::GameEvent,
 };
 
-use super::{Object, health::Health, weapon::{Weapon, WeaponInstance, WeaponSlot}, grenade::Utilities};
+use super::{Object, health::Health, weapon::{Weapon, WeaponInstance, WeaponSlot, WEAPONS, DEFAULT_MELEE}, grenade::Utilities};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
@@ -19,10 +17,18 @@ pub struct Player {
     pub wep: WepSlots,
     #[serde(skip)]
     pub health: Health,
+    /// Factor on all damage dealt to this player
+    #[serde(skip, default = "full_damage")]
+    pub damage_taken: f32,
+}
+
+#[inline]
+fn full_damage() -> f32 {
+    1.
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ActiveSlot {
     Knife = 0,
     Holster = 1,
@@ -50,15 +56,30 @@ impl Default for ActiveSlot {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct WepSlots {
     pub active: ActiveSlot,
     pub utilities: Utilities,
+    /// Used when the knife slot is active, there's always one
+    pub melee: WeaponInstance<'static>,
     pub holster: Option<WeaponInstance<'static>>,
     pub holster2: Option<WeaponInstance<'static>>,
     pub sling: Option<WeaponInstance<'static>>,
 }
 
+impl Default for WepSlots {
+    fn default() -> Self {
+        WepSlots {
+            active: ActiveSlot::default(),
+            utilities: Utilities::default(),
+            melee: WEAPONS[DEFAULT_MELEE].make_instance(),
+            holster: None,
+            holster2: None,
+            sling: None,
+        }
+    }
+}
+
 impl WepSlots {
     #[inline(always)]
     pub fn slot_has_weapon(&self, new_active: ActiveSlot) -> bool {
@@ -97,6 +118,39 @@ impl WepSlots {
         }
         wep
     }
+    #[inline]
+    pub fn get(&self, slot: ActiveSlot) -> Option<&WeaponInstance<'static>> {
+        match slot {
+            ActiveSlot::Knife => None,
+            ActiveSlot::Holster => self.holster.as_ref(),
+            ActiveSlot::Holster2 => self.holster2.as_ref(),
+            ActiveSlot::Sling => self.sling.as_ref(),
+        }
+    }
+    /// The slot with the best weapon that still has ammo, the knife if there's none
+    pub fn best_slot(&self) -> ActiveSlot {
+        [ActiveSlot::Holster, ActiveSlot::Holster2, ActiveSlot::Sling].iter()
+            .filter_map(|&slot| self.get(slot).filter(|wep| !wep.is_empty()).map(|wep| (slot, wep.weapon.power())))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(slot, _)| slot)
+            .unwrap_or(ActiveSlot::Knife)
+    }
+    /// How good the best weapon that still has ammo is, 0 if there's none
+    #[inline]
+    pub fn best_power(&self) -> f32 {
+        self.get(self.best_slot()).map(|wep| wep.weapon.power()).unwrap_or(0.)
+    }
+    /// The weapon of the same kind as `weapon` if there's one in any slot
+    pub fn find(&self, weapon: &Weapon) -> Option<&WeaponInstance<'static>> {
+        self.holster.iter().chain(&self.holster2).chain(&self.sling)
+            .find(|wep| wep.weapon.id == weapon.id)
+    }
+    /// The weapon of the same kind as `weapon` if there's one in any slot
+    pub fn find_mut(&mut self, weapon: &Weapon) -> Option<&mut WeaponInstance<'static>> {
+        let WepSlots{holster, holster2, sling, ..} = self;
+        holster.iter_mut().chain(holster2.iter_mut()).chain(sling.iter_mut())
+            .find(|wep| wep.weapon.id == weapon.id)
+    }
     #[inline(always)]
     pub fn get_active(&self) -> Option<&WeaponInstance<'static>> {
         match self.active {
@@ -115,8 +169,17 @@ impl WepSlots {
             ActiveSlot::Sling => self.sling.as_mut(),
         }
     }
+    /// Whether `weapon` can be taken without swapping out anything but the default melee weapon
+    pub fn has_room_for(&self, weapon: &Weapon) -> bool {
+        match weapon.slot {
+            WeaponSlot::Melee => self.melee.weapon.id == DEFAULT_MELEE,
+            WeaponSlot::Holster => self.holster.is_none() || self.holster2.is_none(),
+            WeaponSlot::Sling => self.sling.is_none(),
+        }
+    }
+    /// The gun slot `weapon` would go into
     #[must_use]
-    pub fn insert(&mut self, weapon: &Weapon) -> &mut Option<WeaponInstance<'static>> {
+    fn insert(&mut self, weapon: &Weapon) -> &mut Option<WeaponInstance<'static>> {
         match (weapon.slot, self) {
             (WeaponSlot::Holster, WepSlots{holster: ref mut s @ None, ..}) |
             (WeaponSlot::Holster, WepSlots{holster2: ref mut s @ None, ..}) |
@@ -124,26 +187,36 @@ impl WepSlots {
             (WeaponSlot::Holster, WepSlots{active: ActiveSlot::Holster2, holster2: ref mut s, ..}) |
             (WeaponSlot::Holster, WepSlots{holster2: ref mut s, ..}) |
             (WeaponSlot::Sling, WepSlots{sling: ref mut s, ..}) => s,
+            (WeaponSlot::Melee, _) => unreachable!("melee weapons have their own slot"),
         }
     }
+    /// Puts the weapon in its slot, giving back what was there
     #[must_use]
-    #[inline]
     pub fn add_weapon(&mut self, wep_instance: WeaponInstance<'static>) -> Option<WeaponInstance<'static>> {
-        std::mem::replace(self.insert(&wep_instance.weapon), Some(wep_instance))
+        if let WeaponSlot::Melee = wep_instance.weapon.slot {
+            Some(std::mem::replace(&mut self.melee, wep_instance))
+        } else {
+            std::mem::replace(self.insert(&wep_instance.weapon), Some(wep_instance))
+        }
     }
 }
 
 impl IntoIterator for WepSlots {
     type IntoIter = Chain<
-        Chain<IntoIter<WeaponInstance<'static>>, IntoIter<WeaponInstance<'static>>>,
+        Chain<
+            Chain<IntoIter<WeaponInstance<'static>>, IntoIter<WeaponInstance<'static>>>,
+            IntoIter<WeaponInstance<'static>>,
+        >,
         IntoIter<WeaponInstance<'static>>,
     >;
     type Item = <Self::IntoIter as Iterator>::Item;
     fn into_iter(self) -> Self::IntoIter {
         #[allow(clippy::unneeded_field_pattern)]
-        let WepSlots{active: _, utilities: _, holster, holster2, sling} = self;
+        let WepSlots{active: _, utilities: _, melee, holster, holster2, sling} = self;
+        // Everyone carries the default melee weapon, so it's not worth dropping
+        let melee = Some(melee).filter(|m| m.weapon.id != DEFAULT_MELEE);
 
-        holster.into_iter().chain(holster2).chain(sling)
+        holster.into_iter().chain(holster2).chain(sling).chain(melee)
     }
 }
 
@@ -154,6 +227,7 @@ impl Player {
             obj,
             wep: Default::default(),
             health: Health::default(),
+            damage_taken: full_damage(),
         }
     }
     #[inline]
@@ -174,6 +248,18 @@ impl Player {
             .. self
         }
     }
+    #[inline]
+    pub fn with_damage_taken(self, damage_taken: f32) -> Self {
+        Self {
+            damage_taken,
+            .. self
+        }
+    }
+    /// Takes `dmg` damage from a weapon, scaled by how much damage this player takes
+    #[inline]
+    pub fn weapon_damage(&mut self, dmg: f32, penetration: f32) {
+        self.health.weapon_damage(self.damage_taken * dmg, penetration);
+    }
 
     #[inline]
     pub fn draw_player(&self, ctx: &mut Context, a: &Assets) -> GameResult<()> {
@@ -181,11 +267,7 @@ impl Player {
     }
     pub fn draw(&self, ctx: &mut Context, a: &Assets, sprite: &str, color: Color) -> GameResult<()> {
         {
-            let hands_sprite = if let Some(wep) = self.wep.get_active() {
-                wep.weapon.hands_sprite
-            } else {
-                "weapons/knife_hands"
-            };
+            let hands_sprite = self.wep.get_active().unwrap_or(&self.wep.melee).weapon.hands_sprite;
 
             let dp = graphics::DrawParam {
                 dest: (self.obj.pos+angle_to_vec(self.obj.rot)*16.).into(),
@@ -198,10 +280,10 @@ impl Player {
         let img = a.get_img(ctx, sprite);
         self.obj.draw(ctx, &*img, color)
     }
-    pub fn update(&mut self, ctx: &mut Context, mplayer: &mut MediaPlayer) -> GameResult<()> {
+    pub fn update(&mut self, events: &mut Vec<GameEvent>) {
         if let Some(wep) = self.wep.get_active_mut() {
-            wep.update(ctx, mplayer)?;
+            wep.update(events);
         }
-        Ok(())
+        self.wep.melee.update(events);
     }
 }
@@ -3,13 +3,11 @@ use std::fmt::{self, Display};
 
 use crate::{
     util::{Point2, angle_to_vec, Sstr},
-    game::DELTA,
-    io::{
-        snd::MediaPlayer,
-        tex::{PosText, Assets},
-    },
+    game::{DELTA, world::GameEvent},
+    io::tex::{PosText, Assets},
 };
-use ggez::{Context, GameResult};
+use ggez::{GameResult, graphics::Color};
+use rand::Rng;
 
 use super::{Object, bullet::Bullet};
 
@@ -18,10 +16,50 @@ use super::{Object, bullet::Bullet};
 pub enum FireMode {
     Automatic,
     SemiAutomatic,
-    BoltAction,
-    PumpAction {
-        shell_load: u16,
-    }
+    /// Fires `count` shots `interval` seconds apart for each pull of the trigger
+    Burst {
+        count: u16,
+        interval: f32,
+    },
+    /// Needs the trigger held for `time` seconds and fires when it's let go
+    Charge {
+        time: f32,
+    },
+    /// Has the bolt worked for `cock_time` seconds after each shot
+    BoltAction {
+        cock_time: f32,
+    },
+    PumpAction,
+    /// Swung at whoever's within `range` and `arc` radians of where it's pointed
+    Melee {
+        range: f32,
+        arc: f32,
+        /// Factor on the damage of a hit from behind
+        backstab: f32,
+        /// Seconds enemies spend winding up a swing before it lands
+        windup: f32,
+        /// Played instead of the shot sound on a hit from behind
+        #[serde(deserialize_with = "crate::util::deserialize_sstr")]
+        backstab_snd: Sstr,
+    },
+}
+
+/// How the pellets of a weapon firing several at once spread out
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Spread {
+    /// Most radians a pellet can go off to either side
+    pub angle: f32,
+    pub pellets: u16,
+    /// Pixels a pellet travels before its damage has halved
+    pub falloff: f32,
+}
+
+/// A streak drawn behind fast bullets to show where they're going
+#[derive(Debug, Clone, Copy)]
+pub struct Tracer {
+    pub color: Color,
+    /// Length in pixels at full bullet speed
+    pub length: f32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -29,6 +67,7 @@ pub enum FireMode {
 pub enum WeaponSlot {
     Holster,
     Sling,
+    Melee,
 }
 
 impl FireMode {
@@ -40,6 +79,37 @@ impl FireMode {
             false
         }
     }
+    #[inline]
+    pub fn is_melee(self) -> bool {
+        if let FireMode::Melee{..} = self {
+            true
+        } else {
+            false
+        }
+    }
+    /// Seconds before the weapon is ready again that the cock sound is played
+    #[inline]
+    fn cock_time(self) -> f32 {
+        if let FireMode::BoltAction{cock_time} = self {
+            cock_time
+        } else {
+            0.
+        }
+    }
+}
+
+impl Display for FireMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            FireMode::Automatic => write!(f, "auto"),
+            FireMode::SemiAutomatic => write!(f, "semi"),
+            FireMode::Burst{count, ..} => write!(f, "burst {}", count),
+            FireMode::Charge{..} => write!(f, "charge"),
+            FireMode::BoltAction{..} => write!(f, "bolt"),
+            FireMode::PumpAction => write!(f, "pump"),
+            FireMode::Melee{..} => write!(f, "melee"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,15 +118,23 @@ pub struct Weapon {
     pub name: Sstr,
     pub clip_size: NonZeroU16,
     pub clips: NonZeroU16,
+    /// Damage of each bullet, or each pellet if there's a spread
     pub damage: f32,
     /// Fraction of armour damage rediverted to hp damage
     pub penetration: f32,
-    /// Time between each shot
+    /// How well bullets punch through walls, compared against the resistance of materials
+    pub penetration_power: f32,
+    /// Time between each shot or swing
     pub fire_rate: f32,
     /// Time to reload a new clip/magazine
     pub reload_time: f32,
+    /// Mode the weapon starts out in, the first of `fire_modes`
     pub fire_mode: FireMode,
+    /// Modes that can be switched between
+    pub fire_modes: Box<[FireMode]>,
     pub shot_snd: Sstr,
+    /// How far away a shot can be heard
+    pub loudness: f32,
     pub cock_snd: Sstr,
     pub reload_snd: Sstr,
     pub click_snd: Sstr,
@@ -66,13 +144,25 @@ pub struct Weapon {
     pub spray_pattern: Box<[f32]>,
     pub spray_decay: f32,
     pub spray_repeat: usize,
+    pub spread: Option<Spread>,
+    /// Most radians shots go off to either side when fired running at full speed
+    pub move_inaccuracy: f32,
     pub bullet_speed: f32,
+    pub bullet_sprite: Sstr,
+    pub tracer: Option<Tracer>,
+    /// Sprite shown at the muzzle for a moment after each shot
+    pub muzzle_flash: Option<Sstr>,
+    /// Sprite of the casing thrown out with each shot
+    pub casing: Option<Sstr>,
     pub slot: WeaponSlot,
 }
 
 mod consts;
 pub use self::consts::*;
 
+/// The melee weapon everyone starts out with
+pub const DEFAULT_MELEE: &str = "knife";
+
 impl Weapon {
     pub fn make_instance(&self) -> WeaponInstance<'_> {
         let cur_clip = self.clip_size.get();
@@ -83,6 +173,9 @@ impl Weapon {
             jerk: 0.,
             jerk_decay: 0.,
             spray_index: 0,
+            mode: 0,
+            burst: 0,
+            charge: 0.,
             ammo: cur_clip*self.clips.get(),
         }
     }
@@ -95,9 +188,42 @@ impl Weapon {
             weapon: self,
         }
     }
+    /// Rough damage per second, used to tell which of two weapons is better
+    pub fn power(&self) -> f32 {
+        let pellets = self.spread.map(|spread| f32::from(spread.pellets)).unwrap_or(1.);
+        self.damage * pellets / self.fire_rate.max(DELTA)
+    }
+    /// How much a melee hit does, `backstab` being whether it's from behind
+    pub fn melee_damage(&self, backstab: bool) -> f32 {
+        match self.fire_mode {
+            FireMode::Melee{backstab: factor, ..} if backstab => factor * self.damage,
+            _ => self.damage,
+        }
+    }
+    /// Whether a melee weapon swung by `by` reaches `target`
+    pub fn in_melee_reach(&self, by: &Object, target: Point2) -> bool {
+        if let FireMode::Melee{range, arc, ..} = self.fire_mode {
+            let dist = target - by.pos;
+            dist.norm() < range && (dist.norm_squared() == 0. || angle_to_vec(by.rot).angle(&dist) <= arc)
+        } else {
+            false
+        }
+    }
+    /// How far a melee weapon reaches, 0 for guns
     #[inline]
-    pub fn get_bullet_spr(&self) -> &str {
-        "common/bullet"
+    pub fn melee_range(&self) -> f32 {
+        if let FireMode::Melee{range, ..} = self.fire_mode {
+            range
+        } else {
+            0.
+        }
+    }
+    /// Sound of a melee hit, `backstab` being whether it's from behind
+    pub fn melee_snd(&self, backstab: bool) -> Sstr {
+        match self.fire_mode {
+            FireMode::Melee{backstab_snd, ..} if backstab => backstab_snd,
+            _ => self.shot_snd,
+        }
     }
 }
 
@@ -111,7 +237,11 @@ pub struct WeaponDrop<'a> {
 
 impl Display for WeaponDrop<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}/{}", self.weapon.name, self.cur_clip, self.ammo)
+        if self.weapon.fire_mode.is_melee() {
+            write!(f, "{}", self.weapon.name)
+        } else {
+            write!(f, "{} {}/{}", self.weapon.name, self.cur_clip, self.ammo)
+        }
     }
 }
 
@@ -123,18 +253,28 @@ pub struct WeaponInstance<'a> {
     pub jerk: f32,
     pub jerk_decay: f32,
     pub spray_index: usize,
+    /// Index of the current mode in `fire_modes`
+    pub mode: usize,
+    /// Shots left of the burst being fired
+    pub burst: u16,
+    /// Seconds the trigger has been held for a charge-up shot
+    pub charge: f32,
     pub weapon: &'a Weapon,
 }
 
 impl Display for WeaponInstance<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}/{}", self.weapon.name, self.cur_clip, self.ammo)
+        if self.weapon.fire_mode.is_melee() {
+            write!(f, "{}", self.weapon.name)
+        } else {
+            write!(f, "{} {}/{}", self.weapon.name, self.cur_clip, self.ammo)
+        }
     }
 }
 
 impl<'a> WeaponInstance<'a> {
     pub fn weapon_text(p: Point2, a: &Assets) -> PosText {
-        a.text(p).and_text("BFG").and_text(" ").and_text("0").and_text("/").and_text("0").and_text(" (").and_text("0").and_text(" ").and_text("0").and_text("s)")
+        a.text(p).and_text("BFG").and_text(" ").and_text("0").and_text("/").and_text("0").and_text(" (").and_text("0").and_text(" ").and_text("0").and_text("s) ").and_text("auto")
     }
     pub fn update_text(&self, text: &mut PosText) -> GameResult<()> {
         text
@@ -143,8 +283,41 @@ impl<'a> WeaponInstance<'a> {
             .update(4, format!("{}", self.ammo))?
             .update(6, format!("{:.3}", self.jerk))?
             .update(8, format!("{:.1}", self.jerk_decay))?;
+        if let FireMode::Charge{time} = self.fire_mode() {
+            text.update(10, format!("charge {:.0}%", 100. * self.charge / time))?;
+        } else {
+            text.update(10, format!("{}", self.fire_mode()))?;
+        }
         Ok(())
     } 
+    /// The mode the weapon is currently set to
+    #[inline]
+    pub fn fire_mode(&self) -> FireMode {
+        self.weapon.fire_modes[self.mode]
+    }
+    /// Switches to the next of the weapon's fire modes if it has more than one
+    pub fn switch_fire_mode(&mut self, events: &mut Vec<GameEvent>) {
+        if self.weapon.fire_modes.len() > 1 {
+            self.mode = (self.mode + 1) % self.weapon.fire_modes.len();
+            self.burst = 0;
+            self.charge = 0.;
+            events.push(GameEvent::Sound(self.weapon.click_snd));
+        }
+    }
+    /// Builds up the charge of a charge-up weapon while the trigger is `held`, losing it when it's not
+    pub fn hold_trigger(&mut self, held: bool) {
+        match self.fire_mode() {
+            FireMode::Charge{time} if held && self.cur_clip > 0 && self.loading_time == 0. => {
+                self.charge = (self.charge + DELTA).min(time);
+            }
+            _ => self.charge = 0.,
+        }
+    }
+    /// Whether there are no bullets left, loaded or not
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cur_clip == 0 && self.ammo == 0
+    }
     pub fn into_drop(self, pos: Point2) -> WeaponDrop<'a> {
         let WeaponInstance{cur_clip, ammo, weapon, ..} = self;
         WeaponDrop {
@@ -162,12 +335,15 @@ impl<'a> WeaponInstance<'a> {
             jerk: 0.,
             jerk_decay: 0.,
             spray_index: 0,
+            mode: 0,
+            burst: 0,
+            charge: 0.,
             cur_clip,
             ammo,
             weapon,
         }
     }
-    pub fn update(&mut self, ctx: &mut Context, mplayer: &mut MediaPlayer) -> GameResult<()> {
+    pub fn update(&mut self, events: &mut Vec<GameEvent>) {
         if self.jerk_decay <= DELTA {
             self.jerk = 0.;
             self.jerk_decay = 0.;
@@ -178,20 +354,22 @@ impl<'a> WeaponInstance<'a> {
         if self.loading_time <= DELTA {
             self.loading_time = 0.;
         } else {
+            let cock_time = self.fire_mode().cock_time().max(DELTA);
+            let cocked = self.loading_time > cock_time;
             self.loading_time -= DELTA;
-            if self.loading_time <= DELTA {
-                mplayer.play(ctx, &self.weapon.cock_snd)?;
+            if cocked && self.loading_time <= cock_time && !self.weapon.fire_mode.is_melee() {
+                events.push(GameEvent::Sound(self.weapon.cock_snd));
             }
         }
-        Ok(())
     }
-    pub fn reload(&mut self, ctx: &mut Context, mplayer: &mut MediaPlayer) -> GameResult<()> {
+    pub fn reload(&mut self, events: &mut Vec<GameEvent>) {
         let clip_size = self.weapon.clip_size.get();
         if self.cur_clip == clip_size || self.ammo == 0 {
-            return Ok(())
+            return
         }
 
         self.loading_time = self.weapon.reload_time;
+        self.burst = 0;
 
         let ammo_to_reload = self.weapon.clip_size.get() - self.cur_clip;
 
@@ -202,7 +380,7 @@ impl<'a> WeaponInstance<'a> {
             self.ammo -= ammo_to_reload;
             self.cur_clip = clip_size;
         }
-        mplayer.play(ctx, &self.weapon.reload_snd)
+        events.push(GameEvent::Sound(self.weapon.reload_snd));
     }
     fn next_jerk(&mut self) -> f32 {
         let jerk = self.jerk;
@@ -216,51 +394,181 @@ impl<'a> WeaponInstance<'a> {
 
         jerk
     }
-    pub fn shoot(&mut self, ctx: &mut Context, mplayer: &mut MediaPlayer) -> GameResult<Option<BulletMaker<'a>>> {
+    /// Readies a melee weapon for another swing if it's not still recovering from the last one,
+    /// returning whether it could be swung
+    pub fn swing(&mut self) -> bool {
+        if self.loading_time == 0. {
+            self.loading_time = self.weapon.fire_rate;
+            true
+        } else {
+            false
+        }
+    }
+    pub fn shoot(&mut self, events: &mut Vec<GameEvent>) -> Option<BulletMaker<'a>> {
+        let fire_mode = self.fire_mode();
+        if let FireMode::Charge{time} = fire_mode {
+            if self.charge < time {
+                return None;
+            }
+            self.charge = 0.;
+        }
         if self.cur_clip > 0 && self.loading_time == 0. {
             self.cur_clip -= 1;
-            if self.cur_clip > 0 {
-                self.loading_time = self.weapon.fire_rate;
+            if let FireMode::Burst{count, ..} = fire_mode {
+                if self.burst == 0 {
+                    self.burst = count;
+                }
+                self.burst -= 1;
+            }
+            if self.cur_clip == 0 {
+                self.burst = 0;
+            } else {
+                self.loading_time = match fire_mode {
+                    FireMode::Burst{interval, ..} if self.burst > 0 => interval,
+                    FireMode::BoltAction{cock_time} => self.weapon.fire_rate + cock_time,
+                    _ => self.weapon.fire_rate,
+                };
             }
 
-            mplayer.play(ctx, &self.weapon.shot_snd)?;
-
-            let jerks = match self.weapon.fire_mode {
-                FireMode::PumpAction{shell_load} => {
-                    (0..shell_load).map(|_| self.next_jerk()).collect()
-                },
-                _ => {
-                    vec![self.next_jerk()]
-                },
-            };
+            events.push(GameEvent::Sound(self.weapon.shot_snd));
 
-            Ok(Some(BulletMaker(self.weapon, jerks)))
+            Some(BulletMaker(self.weapon, self.next_jerk()))
         } else {
             if self.cur_clip == 0 {
-                mplayer.play(ctx, &self.weapon.click_snd)?;
+                events.push(GameEvent::Sound(self.weapon.click_snd));
             }
-            Ok(None)
+            None
         }
     }
 }
 
-// Weapon, jerks
+// Weapon, jerk
 // jerk is used to adjust the target position
-pub struct BulletMaker<'a>(&'a Weapon, Vec<f32>);
+pub struct BulletMaker<'a>(&'a Weapon, f32);
 impl<'a> BulletMaker<'a> {
-    pub fn make(self, obj: Object) -> impl Iterator<Item=Bullet<'a>> {
-        let BulletMaker(weapon, jerks) = self;
+    /// Makes the bullets or pellets of a shot, sending each up to `inaccuracy` radians off to either side
+    pub fn make<R: Rng>(self, obj: Object, rng: &mut R, inaccuracy: f32) -> Vec<Bullet<'a>> {
+        let BulletMaker(weapon, jerk) = self;
+        let (pellets, angle) = weapon.spread.map(|spread| (spread.pellets, spread.angle)).unwrap_or((1, 0.));
+        let spread = angle + inaccuracy;
 
-        jerks.into_iter().map(move |jerk| {
+        (0..pellets).map(|_| {
             let mut obj = obj.clone();
 
             obj.rot += jerk;
+            if spread > 0. {
+                obj.rot += rng.gen_range(-spread, spread);
+            }
             Bullet {
                 vel: weapon.bullet_speed * angle_to_vec(obj.rot),
+                damage: weapon.damage,
+                origin: obj.pos,
                 obj,
                 weapon,
             }
-        })
+        }).collect()
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The m4a1 with only the given fire mode
+    fn m4a1_with(mode: FireMode) -> Weapon {
+        Weapon {
+            fire_mode: mode,
+            fire_modes: vec![mode].into_boxed_slice(),
+            .. WEAPONS["m4a1"].clone()
+        }
+    }
+
+    fn ticks(seconds: f32) -> usize {
+        (seconds / DELTA).ceil() as usize
+    }
+
+    fn is_sound(event: &GameEvent, snd: &str) -> bool {
+        match event {
+            GameEvent::Sound(s) => *s == snd,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn burst_keeps_firing_until_done() {
+        let weapon = m4a1_with(FireMode::Burst{count: 3, interval: 0.1});
+        let mut wep = weapon.make_instance();
+        let mut events = Vec::new();
+
+        assert!(wep.shoot(&mut events).is_some());
+        let mut shots = vec![0];
+        for tick in 1..ticks(1.) {
+            wep.update(&mut events);
+            // Like the world does, bursts go on without the trigger being pulled again
+            if wep.burst > 0 && wep.shoot(&mut events).is_some() {
+                shots.push(tick);
+            }
+        }
+
+        assert_eq!(shots.len(), 3, "shot at ticks {:?}", shots);
+        assert!(shots.windows(2).all(|w| w[1] - w[0] >= ticks(0.1) - 1), "shot at ticks {:?}", shots);
+        assert_eq!(wep.cur_clip, weapon.clip_size.get() - 3);
+        assert_eq!(wep.burst, 0);
+    }
+
+    #[test]
+    fn charge_needs_the_trigger_held() {
+        let weapon = m4a1_with(FireMode::Charge{time: 0.5});
+        let mut wep = weapon.make_instance();
+        let mut events = Vec::new();
+
+        assert!(wep.shoot(&mut events).is_none(), "fired without charging");
+
+        for _ in 0..ticks(0.25) {
+            wep.hold_trigger(true);
+        }
+        assert!(wep.charge > 0.);
+        assert!(wep.shoot(&mut events).is_none(), "fired half charged");
+        wep.hold_trigger(false);
+        assert_eq!(wep.charge, 0., "charge kept after letting go");
+
+        for _ in 0..ticks(0.5) {
+            wep.hold_trigger(true);
+        }
+        assert!(wep.shoot(&mut events).is_some(), "didn't fire fully charged");
+        assert_eq!(wep.charge, 0.);
+        assert_eq!(wep.cur_clip, weapon.clip_size.get() - 1);
+    }
+
+    #[test]
+    fn bolt_action_cocks_before_next_shot() {
+        let weapon = m4a1_with(FireMode::BoltAction{cock_time: 0.6});
+        let mut wep = weapon.make_instance();
+        let mut events = Vec::new();
+
+        assert!(wep.shoot(&mut events).is_some());
+        assert_eq!(wep.loading_time, weapon.fire_rate + 0.6);
+
+        events.clear();
+        let mut cocked_at = None;
+        let mut ready_at = None;
+        for tick in 0..ticks(2.) {
+            wep.update(&mut events);
+            if cocked_at.is_none() && events.iter().any(|e| is_sound(e, weapon.cock_snd)) {
+                cocked_at = Some(tick);
+            }
+            if wep.loading_time == 0. {
+                ready_at = Some(tick);
+                break;
+            }
+            assert!(wep.shoot(&mut Vec::new()).is_none(), "fired while working the bolt");
+        }
+
+        let cocked_at = cocked_at.expect("bolt never cocked");
+        let ready_at = ready_at.expect("never ready to fire again");
+        assert!(cocked_at < ready_at);
+        assert!(ready_at - cocked_at + 1 >= ticks(0.6) - 1, "cocked at {} but ready at {}", cocked_at, ready_at);
+        assert!(wep.shoot(&mut events).is_some());
+    }
+}
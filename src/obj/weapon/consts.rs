@@ -1,13 +1,13 @@
-use super::{FireMode, Weapon, WeaponSlot};
-use crate::util::{sstr, add_sstr, Sstr};
+use super::{FireMode, Spread, Tracer, Weapon, WeaponSlot};
+use crate::util::{sstr, add_sstr, Sstr, DEG2RAD};
 
 use lazy_static::lazy_static;
+use ggez::graphics::Color;
 
 use std::fs::File;
 use std::io::Read;
 use std::num::NonZeroU16;
 use std::collections::HashMap;
-use std::f32::consts::PI;
 
 lazy_static!{
     pub static ref WEAPONS: HashMap<&'static str, Weapon> = {
@@ -27,21 +27,28 @@ lazy_static!{
 pub struct WeaponTemplate {
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
     name: Sstr,
+    #[serde(default = "def_one")]
     clip_size: NonZeroU16,
+    #[serde(default = "def_one")]
     clips: NonZeroU16,
     damage: f32,
     penetration: f32,
+    #[serde(default)]
+    penetration_power: f32,
     fire_rate: f32,
+    #[serde(default)]
     reload_time: f32,
-    fire_mode: FireMode,
+    fire_mode: FireModes,
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
     shot_snd: Sstr,
+    loudness: f32,
     #[serde(default = "def_cock")]
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
     cock_snd: Sstr,
     #[serde(default = "def_reload")]
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
     reload_snd: Sstr,
+    #[serde(default = "def_click")]
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
     click_snd: Sstr,
     #[serde(default = "def_impact")]
@@ -49,18 +56,56 @@ pub struct WeaponTemplate {
     impact_snd: Sstr,
     #[serde(deserialize_with = "crate::util::deserialize_sstr")]
     entity_sprite: Sstr,
+    #[serde(default = "def_spray")]
     spray_pattern: Vec<f32>,
+    #[serde(default)]
     spray_decay: f32,
+    #[serde(default = "def_repeat")]
     spray_repeat: usize,
+    #[serde(default)]
+    spread: Option<Spread>,
+    #[serde(default = "def_inaccuracy")]
+    move_inaccuracy: f32,
     #[serde(default = "def_speed")]
     bullet_speed: f32,
+    #[serde(default = "def_bullet")]
+    #[serde(deserialize_with = "crate::util::deserialize_sstr")]
+    bullet_sprite: Sstr,
+    #[serde(default)]
+    tracer: Option<TracerTemplate>,
+    #[serde(default)]
+    muzzle_flash: Option<Box<str>>,
+    #[serde(default)]
+    casing: Option<Box<str>>,
     slot: WeaponSlot,
 }
 
+#[derive(Serialize, Deserialize)]
+struct TracerTemplate {
+    color: [f32; 4],
+    length: f32,
+}
+
+/// Either a single fire mode or a list of ones that can be switched between
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FireModes {
+    One(FireMode),
+    Several(Vec<FireMode>),
+}
+
 #[inline]
 const fn def_speed() -> f32 {
     1200.
 }
+#[inline]
+fn def_bullet() -> Sstr {
+    add_sstr("common/bullet")
+}
+#[inline]
+const fn def_inaccuracy() -> f32 {
+    4.
+}
 fn def_cock() -> Sstr {
     add_sstr("cock")
 }
@@ -72,8 +117,22 @@ fn def_reload() -> Sstr {
 fn def_impact() -> Sstr {
     add_sstr("impact")
 }
-
-const DEG2RAD: f32 = PI / 180.;
+#[inline]
+fn def_click() -> Sstr {
+    add_sstr("click_pistol")
+}
+#[inline]
+fn def_one() -> NonZeroU16 {
+    NonZeroU16::new(1).unwrap()
+}
+#[inline]
+fn def_spray() -> Vec<f32> {
+    vec![0.]
+}
+#[inline]
+const fn def_repeat() -> usize {
+    1
+}
 
 impl WeaponTemplate {
     fn build(self, id: &'static str) -> Weapon {
@@ -83,10 +142,12 @@ impl WeaponTemplate {
             clips,
             damage,
             penetration,
+            penetration_power,
             fire_rate,
             reload_time,
             fire_mode,
             shot_snd,
+            loudness,
             cock_snd,
             reload_snd,
             click_snd,
@@ -95,10 +156,26 @@ impl WeaponTemplate {
             spray_pattern,
             spray_decay,
             spray_repeat,
+            spread,
+            move_inaccuracy,
             bullet_speed,
+            bullet_sprite,
+            tracer,
+            muzzle_flash,
+            casing,
             slot
         } = self;
 
+        let fire_modes: Box<[FireMode]> = match fire_mode {
+            FireModes::One(fire_mode) => vec![fire_mode],
+            FireModes::Several(fire_modes) => fire_modes,
+        }.into_iter().map(|fire_mode| match fire_mode {
+            FireMode::Melee{range, arc, backstab, windup, backstab_snd} => {
+                FireMode::Melee{range, arc: arc * DEG2RAD, backstab, windup, backstab_snd}
+            }
+            fire_mode => fire_mode,
+        }).collect();
+
         Weapon {
             id,
             name,
@@ -106,10 +183,13 @@ impl WeaponTemplate {
             clips,
             damage,
             penetration,
+            penetration_power,
             fire_rate,
             reload_time,
-            fire_mode,
+            fire_mode: *fire_modes.first().expect("at least one fire mode"),
+            fire_modes,
             shot_snd,
+            loudness,
             cock_snd,
             reload_snd,
             click_snd,
@@ -119,7 +199,13 @@ impl WeaponTemplate {
             spray_pattern: spray_pattern.into_iter().map(|deg| deg * DEG2RAD).collect(),
             spray_decay,
             spray_repeat,
+            spread: spread.map(|spread| Spread{angle: spread.angle * DEG2RAD, .. spread}),
+            move_inaccuracy: move_inaccuracy * DEG2RAD,
             bullet_speed,
+            bullet_sprite,
+            tracer: tracer.map(|TracerTemplate{color: [r, g, b, a], length}| Tracer{color: Color{r, g, b, a}, length}),
+            muzzle_flash: muzzle_flash.map(sstr),
+            casing: casing.map(sstr),
             slot
         }
     }
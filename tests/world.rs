@@ -0,0 +1,342 @@
+//! Plays levels headlessly by stepping the world with scripted input
+
+use std::f32::consts::PI;
+
+use topskud::{
+    util::{Point2, Vector2, angle_from_vec, angle_to_vec, DEG2RAD},
+    obj::{
+        Object,
+        door::Door,
+        enemy::{Enemy, Behaviour, Patrol, Waypoint, ARCHETYPES},
+        weapon::WEAPONS,
+        grenade::{Grenade, GrenadeState},
+    },
+    game::{
+        difficulty::Difficulty,
+        world::{Level, World, GameEvent, Input, Action, tile_centre},
+    },
+};
+
+/// Steps in ten seconds of play
+const TEN_SECONDS: usize = 600;
+
+fn world(level: &str) -> World {
+    let level = Level::load(format!("tests/levels/{}.lvl", level)).unwrap();
+    World::new(level, None, 0, Difficulty::default_difficulty())
+}
+
+fn is_sound(event: &GameEvent, snd: &str) -> bool {
+    match event {
+        GameEvent::Sound(s) => *s == snd,
+        _ => false,
+    }
+}
+
+fn is_victory(event: &GameEvent) -> bool {
+    match event {
+        GameEvent::Victory => true,
+        _ => false,
+    }
+}
+
+fn is_death(event: &GameEvent) -> bool {
+    match event {
+        GameEvent::Death => true,
+        _ => false,
+    }
+}
+
+#[test]
+fn shooting_an_enemy_kills_it() {
+    let mut world = world("range");
+    assert!(world.player.wep.get_active().is_some(), "player didn't pick up the glock at the start");
+
+    let mut died = false;
+    let mut won = false;
+    for tick in 0..TEN_SECONDS {
+        let mut input = Input::default();
+        if let Some(enemy) = world.enemies.first() {
+            input.aim = angle_from_vec(enemy.pl.obj.pos - world.player.obj.pos);
+        }
+        // Shoot at the enemy as fast as the glock allows
+        if tick % 20 == 0 {
+            input.actions.push(Action::Shoot);
+        }
+        let events = world.step(&input);
+
+        assert!(!events.iter().any(is_death), "player died");
+        died |= events.iter().any(|e| is_sound(e, "death"));
+        won |= events.iter().any(is_victory);
+        if died && won {
+            break;
+        }
+    }
+
+    assert!(died, "enemy never died");
+    assert!(world.enemies.is_empty());
+    assert!(won, "killing the only enemy didn't win the level");
+}
+
+#[test]
+fn shooting_through_a_wood_wall() {
+    let level = Level::load("tests/levels/wood.toml").unwrap();
+    let mut world = World::new(level, None, 0, Difficulty::default_difficulty());
+    assert_eq!(world.player.wep.get_active().map(|wep| wep.weapon.id), Some("arwp"));
+
+    let mut killed = false;
+    for tick in 0..TEN_SECONDS {
+        let mut input = Input::default();
+        if let Some(enemy) = world.enemies.first() {
+            input.aim = angle_from_vec(enemy.pl.obj.pos - world.player.obj.pos);
+        }
+        // Slow enough for the bolt to be worked and the recoil to settle
+        if tick % 90 == 0 {
+            input.actions.push(Action::Shoot);
+        }
+        world.step(&input);
+
+        if world.enemies.is_empty() {
+            killed = true;
+            break;
+        }
+    }
+
+    assert!(killed, "enemy behind the wood wall never died");
+}
+
+#[test]
+fn reaching_the_exit_wins() {
+    let mut world = world("corridor");
+    let input = Input {
+        movement: Vector2::new(1., 0.),
+        .. Input::default()
+    };
+
+    let won = (0..TEN_SECONDS).any(|_| world.step(&input).iter().any(is_victory));
+
+    assert!(won, "never got to the exit");
+    assert!((world.exit.unwrap() - world.player.obj.pos).norm() < 32.);
+}
+
+#[test]
+fn one_grenade_kills_two_enemies() {
+    let mut world = world("range");
+    let mut second = world.enemies[0].clone();
+    second.pl.obj.pos.y += 32.;
+    world.enemies.push(second);
+
+    // Goes off right away between the two of them
+    world.grenades.push(Grenade {
+        obj: Object::new(Point2::new(304., 96.)),
+        vel: Vector2::new(0., 0.),
+        state: GrenadeState::Fused{fuse: 0.},
+    });
+
+    let mut deaths = 0;
+    for _ in 0..TEN_SECONDS {
+        let events = world.step(&Input::default());
+        deaths += events.iter().filter(|e| is_sound(e, "death")).count();
+        if world.enemies.is_empty() {
+            break;
+        }
+    }
+
+    assert_eq!(deaths, 2);
+    assert!(world.enemies.is_empty());
+    assert_eq!(world.corpses.len(), 2);
+}
+
+#[test]
+fn enemy_opens_door_closed_in_front_of_it() {
+    let mut level = Level::load("tests/levels/corridor.lvl").unwrap();
+    let mut door = Door::new(6, 1, 0.);
+    door.open = true;
+    level.doors.push(door);
+    let mut guard = Enemy::new(Object::new(tile_centre(3, 1)), &ARCHETYPES["guard"]);
+    guard.patrol = Patrol::new(vec![Waypoint::new(tile_centre(10, 1))]);
+    level.enemies.push(guard);
+    let mut world = World::new(level, None, 0, Difficulty::default_difficulty());
+
+    // Walk until the path past the open door has been planned
+    while world.enemies[0].pl.obj.pos.x < 160. {
+        world.step(&Input::default());
+    }
+    world.doors[0].open = false;
+    world.grid.set_door((6, 1), true);
+
+    let passed = (0..TEN_SECONDS).any(|_| {
+        world.step(&Input::default());
+        world.enemies[0].pl.obj.pos.x > 240.
+    });
+
+    assert!(passed, "enemy got stuck at the door");
+    assert!(world.doors[0].open);
+}
+
+#[test]
+fn enemy_turns_before_shooting_at_the_side() {
+    let mut world = world("range");
+    let player_pos = world.player.obj.pos;
+    let enemy = &mut world.enemies[0];
+    let _default = enemy.pl.wep.add_weapon(WEAPONS["glock"].make_instance());
+    enemy.pl.wep.init_active();
+    enemy.behaviour = Behaviour::Combat{pos: player_pos, vel: Vector2::new(0., 0.)};
+    // Long past reacting to the player
+    enemy.spotted = 10.;
+    // The player is in the peripheral vision but outside of the view angle
+    let to_player = angle_from_vec(player_pos - enemy.pl.obj.pos);
+    enemy.pl.obj.rot = to_player + (enemy.kind.view_angle + enemy.kind.peripheral_angle) / 2.;
+    let view_angle = enemy.kind.view_angle;
+
+    let mut shot = false;
+    for _ in 0..TEN_SECONDS {
+        // Enemies shoot before they move
+        let enemy_pos = world.enemies[0].pl.obj.pos;
+        for event in world.step(&Input::default()) {
+            if let GameEvent::Shot(bullet, _) = event {
+                let to_player = world.player.obj.pos - enemy_pos;
+                let angle = angle_to_vec(bullet.rot).angle(&to_player);
+                assert!(angle <= view_angle + DEG2RAD, "shot {} degrees away from the player", angle / DEG2RAD);
+                shot = true;
+            }
+        }
+        if shot {
+            break;
+        }
+    }
+
+    assert!(shot, "enemy never shot");
+}
+
+#[test]
+fn enemy_investigates_a_noise() {
+    let mut world = world("range");
+    let noise = world.player.obj.pos;
+    let start = world.enemies[0].pl.obj.pos;
+    // Shoot the wall behind the player, out of sight of the enemy facing the other way
+    let input = Input {
+        aim: PI,
+        .. Input::default()
+    };
+    world.step(&Input {
+        actions: vec![Action::Shoot],
+        .. input.clone()
+    });
+
+    assert_eq!(world.enemies[0].behaviour.name(), "investigate");
+
+    for _ in 0..60 {
+        world.step(&input);
+    }
+    let enemy = &world.enemies[0];
+    assert!((enemy.pl.obj.pos - noise).norm() < (start - noise).norm() - 32., "enemy didn't go towards the noise");
+}
+
+#[test]
+fn shot_enemy_takes_cover_and_peeks() {
+    let level = Level::load("tests/levels/cover.toml").unwrap();
+    let mut world = World::new(level, None, 0, Difficulty::default_difficulty());
+
+    let mut behaviours = Vec::new();
+    for tick in 0..TEN_SECONDS / 2 {
+        let enemy = &world.enemies[0];
+        let input = Input {
+            aim: angle_from_vec(enemy.pl.obj.pos - world.player.obj.pos),
+            actions: if tick == 30 { vec![Action::Shoot] } else { Vec::new() },
+            .. Input::default()
+        };
+        world.step(&input);
+
+        let name = world.enemies[0].behaviour.name();
+        if behaviours.last() != Some(&name) {
+            behaviours.push(name);
+        }
+        if name == "peek" {
+            break;
+        }
+    }
+
+    let cover = behaviours.iter().position(|&b| b == "take cover").expect("enemy didn't take cover");
+    assert_eq!(behaviours[cover - 1], "combat");
+    assert_eq!(behaviours[cover + 1..], ["peek"]);
+    // Hiding behind the pillar north of the enemy
+    let enemy = &world.enemies[0];
+    assert!(enemy.pl.obj.pos.y < 96., "enemy isn't behind the pillar: {:?}", enemy.pl.obj.pos);
+}
+
+#[test]
+fn unarmed_enemy_winds_up_and_stabs() {
+    let mut world = world("range");
+    let player = world.player.obj.pos;
+    let enemy = &mut world.enemies[0];
+    assert!(enemy.pl.wep.get_active().is_none());
+    enemy.pl.obj.pos = player + Vector2::new(40., 0.);
+    enemy.pl.obj.rot = PI;
+    enemy.behaviour = Behaviour::Melee{pos: player};
+    enemy.spotted = 10.;
+    let knife = enemy.pl.wep.melee.weapon;
+    let health = world.player.health.hp;
+
+    let mut wound_up = false;
+    for _ in 0..TEN_SECONDS / 5 {
+        let events = world.step(&Input::default());
+        let enemy = &world.enemies[0];
+        assert_eq!(enemy.behaviour.name(), "melee");
+
+        if world.player.health.hp < health {
+            assert!(wound_up, "enemy stabbed without winding up");
+            assert!(events.iter().any(|e| is_sound(e, knife.melee_snd(false))));
+            return;
+        }
+        wound_up |= enemy.windup.is_some();
+    }
+    panic!("enemy never stabbed the player");
+}
+
+#[test]
+fn enemy_picks_up_a_better_gun() {
+    let mut world = world("range");
+    let player = world.player.obj.pos;
+    let enemy = &mut world.enemies[0];
+    let _default = enemy.pl.wep.add_weapon(WEAPONS["glock"].make_instance());
+    enemy.pl.wep.init_active();
+    enemy.behaviour = Behaviour::Combat{pos: player, vel: Vector2::new(0., 0.)};
+    let ak = &WEAPONS["ak47"];
+    assert!(enemy.wants(ak));
+    let drop = enemy.pl.obj.pos + Vector2::new(32., 0.);
+    world.weapons.push(ak.make_drop(drop));
+
+    world.step(&Input::default());
+    assert_eq!(world.enemies[0].behaviour.name(), "scavenge");
+
+    for _ in 0..TEN_SECONDS / 5 {
+        world.step(&Input::default());
+        if world.enemies[0].behaviour.name() != "scavenge" {
+            break;
+        }
+    }
+    let enemy = &world.enemies[0];
+    assert_eq!(enemy.pl.wep.get_active().map(|wep| wep.weapon.id), Some("ak47"));
+    assert!(!world.weapons.iter().any(|w| w.pos == drop));
+}
+
+#[test]
+fn ally_raises_the_alarm() {
+    let level = Level::load("tests/levels/alarm.toml").unwrap();
+    let mut world = World::new(level, None, 0, Difficulty::default_difficulty());
+
+    for _ in 0..TEN_SECONDS {
+        let events = world.step(&Input::default());
+        if events.iter().any(|e| is_sound(e, "ding")) {
+            assert!(world.alarms[0].raised);
+            assert!(world.alarms[0].in_reach(world.enemies[1].pl.obj.pos));
+            assert!(world.enemies.iter().all(|e| e.behaviour.is_alert()));
+            return;
+        }
+        // The one who spotted the player keeps fighting while the ally runs for the alarm
+        if world.enemies[0].behaviour.is_alert() {
+            assert_eq!(world.enemies[1].behaviour.name(), "raise alarm");
+        }
+    }
+    panic!("alarm wasn't raised");
+}